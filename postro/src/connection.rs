@@ -2,47 +2,72 @@
 use bytes::{Buf, BytesMut};
 use lru::LruCache;
 use std::{
+    collections::{HashMap, VecDeque},
     future::Ready,
     io,
     num::NonZeroUsize,
     task::{Context, Poll, ready},
-    time::Instant,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use crate::{
     Result,
-    common::{span, verbose},
+    error::ErrorKind,
+    common::{ByteStr, span, unit_error, verbose},
     executor::Executor,
-    net::Socket,
+    net::{self, Socket, tls::TlsError},
     phase,
+    sql::SqlExt,
     postgres::{
-        BackendProtocol, ErrorResponse, FrontendProtocol, NoticeResponse, backend, frontend,
+        BackendProtocol, ErrorResponse, FrontendProtocol, NoticeResponse, ProtocolVersion, backend,
+        frontend,
     },
     statement::StatementName,
     transport::{PgTransport, PgTransportExt},
 };
 
+mod cancel;
 mod config;
+mod credential;
+mod notice;
+mod notification;
+mod passfile;
+mod service;
 
-pub use config::{Config, ParseError};
+pub use config::{
+    ChannelBinding, Config, LoadBalanceHosts, ParseError, SslMode, SslNegotiation, TargetSessionAttrs,
+};
+pub use cancel::CancelToken;
+pub use credential::{CredentialError, CredentialFuture, CredentialProvider};
+pub use notice::NoticeHandler;
+pub use notification::{Notification, Notifications};
+pub(crate) use credential::Credential;
+pub(crate) use notice::NoticeCallback;
 
 const DEFAULT_BUF_CAPACITY: usize = 1024;
-const DEFAULT_PREPARED_STMT_CACHE: NonZeroUsize = NonZeroUsize::new(24).unwrap();
+pub(crate) const DEFAULT_PREPARED_STMT_CACHE: usize = 24;
 
 /// Postgres Connection.
 ///
 /// # Features
 ///
-/// Connection cache a prepared statement. To opt out, use [`once`][1] when querying.
+/// Connection cache a prepared statement, keyed by its sql. To opt out, use [`once`][1] when
+/// querying. The cache size is configured via [`Config::with_statement_cache_size`].
 ///
-/// Connection handle `NoticeResponse` message. If the `log` feature is enabled,
-/// `NoticeResponse` will be logged, otherwise it ignored.
+/// Connection handle `NoticeResponse` message. If a [`NoticeHandler`] is set, the notice
+/// is passed to it, otherwise if the `log` feature is enabled, `NoticeResponse` will be
+/// logged, otherwise it ignored.
 ///
 /// Connection handle `Sync` after receive an `ErrorResponse` message.
 /// This is postgres specific and happens transparently, most users
 /// does not need to worry about this.
 ///
-/// Connection will also consume `ParameterStatus` message
+/// Connection will also consume `ParameterStatus` message, the reported value
+/// can be retrieved via [`Connection::parameter`].
+///
+/// Connection buffers `NotificationResponse` message, the notifications can be
+/// received via [`Connection::notifications`].
 ///
 /// # Pending Messages
 ///
@@ -69,12 +94,23 @@ pub struct Connection {
     write_buf: BytesMut,
 
     // feature
-    stmts: LruCache<u64, StatementName>,
+    stmts: Option<LruCache<String, StatementName>>,
+    stmt_id: u64,
+    stmt_cache_hits: u64,
+    stmt_cache_misses: u64,
+    params: HashMap<String, String>,
+    notifications: VecDeque<Notification>,
+    notice_handler: Option<NoticeCallback>,
+    captured_notices: Option<Vec<NoticeResponse>>,
+    query_timeout: Option<Duration>,
 
     // diagnostic
     connected_at: Instant,
     sync_pending: usize,
-    backend_key: backend::BackendKeyData,
+    /// `Sync` or `Query` sent which `ReadyForQuery` is not yet received
+    syncs: usize,
+    cancel_token: CancelToken,
+    protocol_version: ProtocolVersion,
 }
 
 impl Connection {
@@ -100,35 +136,199 @@ impl Connection {
 
     /// Connect to postgres server with provided config.
     ///
+    /// If multiple hosts are configured, each host is tried in order, or in random order
+    /// with [`LoadBalanceHosts::Random`], until one is connected and satisfy the
+    /// [`TargetSessionAttrs`].
+    ///
+    /// The `connect_timeout` applies to each host, which covers the socket connection,
+    /// SSL negotiation and the startup handshake.
+    ///
     /// # Panics
     ///
     /// Panics if `tokio` feature is not enabled.
     pub async fn connect_with(config: Config) -> Result<Self> {
-        let socket = if cfg!(unix) && config.host == "localhost" {
-            let socket = Socket::connect_socket(&(format!("/run/postgresql/.s.PGSQL.{}",config.port))).await;
-            match socket {
-                Ok(ok) => ok,
-                Err(_) => Socket::connect_tcp(&config.host, config.port).await?,
+        let mut hosts = config.targets()?;
+        // shared with cancel token
+        let config = Arc::new(config);
+
+        if config.load_balance_hosts == LoadBalanceHosts::Random {
+            // Fisher-Yates shuffle
+            for i in (1..hosts.len()).rev() {
+                let j = getrandom::u32().expect("failed to generate random number") as usize % (i + 1);
+                hosts.swap(i, j);
             }
-        } else {
-            Socket::connect_tcp(&config.host, config.port).await?
+        }
+
+        // `prefer-standby` first try to find a standby, then fallback to any server
+        let passes: &[TargetSessionAttrs] = match config.target_session_attrs {
+            TargetSessionAttrs::PreferStandby => &[TargetSessionAttrs::Standby, TargetSessionAttrs::Any],
+            ref attrs => std::slice::from_ref(attrs),
+        };
+
+        let mut last_err = None;
+
+        for attrs in passes {
+            for (host, addr, port) in &hosts {
+                let connect = async {
+                    let mut conn = Self::connect_host(&config, host, addr.as_ref(), *port).await?;
+                    let matched = conn.check_session_attrs(*attrs).await?;
+                    Ok::<_, crate::Error>((conn, matched))
+                };
+
+                match net::timeout(config.connect_timeout, connect).await.map_err(Into::into).and_then(|e|e) {
+                    Ok((conn, true)) => return Ok(conn),
+                    Ok((conn, false)) => {
+                        last_err = Some(SessionAttrsMismatch.into());
+                        let _ = conn.close().await;
+                    },
+                    Err(err) => {
+                        #[cfg(feature = "log")]
+                        log::debug!("failed to connect to {host}:{port}: {err}");
+                        last_err = Some(err);
+                    },
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(||SessionAttrsMismatch.into()))
+    }
+}
+
+impl Connection {
+    /// Connect to `host`, or to its numeric `addr` if any, where `host` is still used for
+    /// SSL verification and password file lookup.
+    async fn connect_host(config: &Arc<Config>, host: &ByteStr, addr: Option<&ByteStr>, port: u16) -> Result<Self> {
+        let socket = Self::connect_socket(config, host, addr.map(ByteStr::as_str), port).await?;
+
+        let socket = match net::tls::negotiate(socket, config, host).await {
+            Ok(ok) => ok,
+            // first try an SSL connection, if the handshake fails, try a non-SSL connection,
+            // local SSL setup error is not retried
+            Err(err) if config.ssl_mode == SslMode::Prefer
+                && config.ssl_negotiation == SslNegotiation::Postgres
+                && matches!(err.kind(), ErrorKind::Tls(TlsError::Handshake(_))) =>
+            {
+                #[cfg(feature = "log")]
+                log::debug!("SSL connection failed, retry without SSL: {err}");
+                Self::connect_socket(config, host, addr.map(ByteStr::as_str), port).await?
+            },
+            Err(err) => return Err(err),
         };
 
+        let mut opt = phase::StartupConfig::from(&**config);
+        // password file is only used when password is not specified
+        if config.pass.is_empty()
+            && config.credential.is_none()
+            && let Some(password) = passfile::lookup(config, host, port)
+        {
+            opt.set_password(password);
+        }
+        match socket.tls_server_end_point() {
+            Some(Ok(data)) => opt.set_tls_server_end_point(data),
+            Some(Err(err)) if config.channel_binding == ChannelBinding::Require => return Err(err.into()),
+            Some(Err(_err)) => {
+                #[cfg(feature = "log")]
+                log::debug!("channel binding is not used: {_err}");
+            },
+            None => {},
+        }
+
+        let tls = socket.is_tls();
         let mut me = Self {
             socket,
             read_buf: BytesMut::with_capacity(DEFAULT_BUF_CAPACITY),
             write_buf: BytesMut::with_capacity(DEFAULT_BUF_CAPACITY),
-            stmts: LruCache::new(DEFAULT_PREPARED_STMT_CACHE),
+            stmts: NonZeroUsize::new(config.statement_cache_size).map(LruCache::new),
+            stmt_id: 0,
+            stmt_cache_hits: 0,
+            stmt_cache_misses: 0,
+            params: HashMap::new(),
+            notifications: VecDeque::new(),
+            notice_handler: config.notice_handler.clone(),
+            captured_notices: None,
+            query_timeout: config.query_timeout,
             connected_at: Instant::now(),
-            backend_key: backend::BackendKeyData { process_id: 0, secret_key: 0 },
+            cancel_token: CancelToken {
+                config: config.clone(),
+                host: host.clone(),
+                addr: addr.cloned(),
+                port,
+                tls,
+                backend_key: backend::BackendKeyData { process_id: 0, secret_key: Default::default() },
+            },
+            protocol_version: ProtocolVersion::default(),
             sync_pending: 0,
+            syncs: 0,
         };
 
-        let res = phase::startup(&config, &mut me).await?;
-        me.backend_key = res.backend_key_data;
+        let res = phase::startup(opt, &mut me).await?;
+        me.cancel_token.backend_key = res.backend_key_data;
+        me.protocol_version = res.protocol_version;
 
         Ok(me)
     }
+
+    /// Connect to `addr` or `host` via TCP, or via Unix domain socket if `host` is an absolute path.
+    ///
+    /// The path is the directory of the socket file, e.g. `/run/postgresql` or `/tmp`.
+    pub(crate) async fn connect_socket(config: &Config, host: &str, addr: Option<&str>, port: u16) -> io::Result<Socket> {
+        if let Some(addr) = addr {
+            Socket::connect_tcp(addr, port, config).await
+        } else if host.starts_with('/') {
+            let dir = host.trim_end_matches('/');
+            Socket::connect_socket(&format!("{dir}/.s.PGSQL.{port}")).await
+        } else {
+            Socket::connect_tcp(host, port, config).await
+        }
+    }
+
+    /// Returns `true` if the session satisfy given [`TargetSessionAttrs`].
+    async fn check_session_attrs(&mut self, attrs: TargetSessionAttrs) -> Result<bool> {
+        let read_only = self.parameter("default_transaction_read_only").map(|e| e == "on");
+        let hot_standby = self.parameter("in_hot_standby").map(|e| e == "on");
+        session_attrs(self, attrs, read_only, hot_standby).await
+    }
+}
+
+/// Returns `true` if the session satisfy given [`TargetSessionAttrs`].
+///
+/// Following libpq, the session is read-only if either `default_transaction_read_only` or
+/// `in_hot_standby` parameter status is on. The server is only queried when the relevant
+/// parameter status is not reported.
+async fn session_attrs<IO: PgTransport>(
+    io: &mut IO,
+    attrs: TargetSessionAttrs,
+    read_only: Option<bool>,
+    hot_standby: Option<bool>,
+) -> Result<bool> {
+    use TargetSessionAttrs::*;
+
+    let read_only = match attrs {
+        Any | PreferStandby => return Ok(true),
+        ReadWrite | ReadOnly => match (read_only, hot_standby) {
+            (None, None) => {
+                let value: String = crate::query_scalar("SHOW transaction_read_only".once(), &mut *io)
+                    .fetch_one()
+                    .await?;
+                value == "on"
+            },
+            _ => read_only == Some(true) || hot_standby == Some(true),
+        },
+        Primary | Standby => match hot_standby {
+            Some(hot_standby) => hot_standby,
+            None => {
+                let value: String = crate::query_scalar("SELECT pg_catalog.pg_is_in_recovery()::text".once(), &mut *io)
+                    .fetch_one()
+                    .await?;
+                value == "true"
+            },
+        },
+    };
+
+    Ok(match attrs {
+        ReadWrite | Primary => !read_only,
+        _ => read_only,
+    })
 }
 
 impl Connection {
@@ -140,9 +340,171 @@ impl Connection {
     /// Get the [`BackendKeyData`][1] retrieved from startup message.
     ///
     /// [1]: backend::BackendKeyData
-    pub fn backend_key(&self) -> backend::BackendKeyData {
-        self.backend_key
+    pub fn backend_key(&self) -> &backend::BackendKeyData {
+        &self.cancel_token.backend_key
+    }
+
+    /// Returns a [`CancelToken`] to cancel the query in progress from another task.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel_token.clone()
+    }
+
+    /// Set the default time limit of each query, see [`Query::timeout`][1].
+    ///
+    /// The initial value is taken from [`Config::with_query_timeout`].
+    ///
+    /// [1]: crate::query::Query::timeout
+    pub fn set_query_timeout(&mut self, timeout: Option<Duration>) {
+        self.query_timeout = timeout;
+    }
+
+    /// Set the [`NoticeHandler`], replacing the one from [`Config::with_notice_handler`].
+    pub fn set_notice_handler(&mut self, handler: impl NoticeHandler) {
+        self.notice_handler = Some(NoticeCallback(Arc::new(handler)));
+    }
+
+    /// Returns the number of prepared statement cache hit.
+    pub fn statement_cache_hits(&self) -> u64 {
+        self.stmt_cache_hits
+    }
+
+    /// Returns the number of prepared statement cache miss.
+    pub fn statement_cache_misses(&self) -> u64 {
+        self.stmt_cache_misses
+    }
+
+    /// Returns the number of cached prepared statement.
+    pub fn statement_cache_len(&self) -> usize {
+        self.stmts.as_ref().map_or(0, LruCache::len)
+    }
+
+    /// Close all cached prepared statement.
+    ///
+    /// The `Close` messages are queued, see [Pending Messages](#pending-messages).
+    pub fn clear_statement_cache(&mut self) {
+        let Some(stmts) = &mut self.stmts else {
+            return;
+        };
+        let names = std::iter::from_fn(|| stmts.pop_lru()).map(|(_, name)| name).collect::<Vec<_>>();
+        if names.is_empty() {
+            return;
+        }
+        for name in &names {
+            self.send(frontend::Close {
+                variant: b'S',
+                name: name.as_str(),
+            });
+        }
+        self.send(frontend::Sync);
+        self.ready_request();
+    }
+
+    /// Deallocate all prepared statement in the session, including the one not prepared by
+    /// the statement cache, and clear the cache.
+    pub async fn deallocate_all(&mut self) -> Result<()> {
+        if let Some(stmts) = &mut self.stmts {
+            stmts.clear();
+        }
+        self.simple_command("DEALLOCATE ALL").await
+    }
+
+    /// Get the protocol version negotiated in startup message.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// Get the current value of run-time parameter reported by the server.
+    ///
+    /// Postgres reports the value at startup and whenever it changes, but only for
+    /// [some parameters][1], e.g. `server_version`, `client_encoding`, `TimeZone`, etc.
+    ///
+    /// [1]: https://www.postgresql.org/docs/current/protocol-flow.html#PROTOCOL-ASYNC
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+
+    /// Get the server version as a number, e.g. `150004` for `15.4`, or `90602` for `9.6.2`.
+    ///
+    /// This is the same format as `server_version_num` setting.
+    pub fn server_version(&self) -> Option<u32> {
+        self.parameter("server_version").and_then(parse_server_version)
+    }
+
+    /// Get the current `TimeZone` of the session.
+    pub fn time_zone(&self) -> Option<&str> {
+        self.parameter("TimeZone")
+    }
+
+    /// Get the current `client_encoding` of the session.
+    pub fn client_encoding(&self) -> Option<&str> {
+        self.parameter("client_encoding")
+    }
+
+    /// Returns `true` if the server is built with 64-bit integer dates and times.
+    pub fn integer_datetimes(&self) -> Option<bool> {
+        self.parameter("integer_datetimes").map(|e|e == "on")
+    }
+
+    fn parameter_status(&mut self, body: bytes::Bytes) -> Result<()> {
+        let backend::ParameterStatus { name, value } = backend::ParameterStatus::decode(
+            backend::ParameterStatus::MSGTYPE,
+            body,
+        )?;
+        verbose!(%name,%value,"parameter status");
+        self.params.insert(name.into_string(), value.into_string());
+        Ok(())
     }
+
+    fn notice(&mut self, body: bytes::Bytes) {
+        let notice = NoticeResponse::new(body);
+        match &mut self.captured_notices {
+            Some(captured) => captured.push(notice),
+            None => self.handle_notice(&notice),
+        }
+    }
+
+    fn handle_notice(&self, notice: &NoticeResponse) {
+        if let Some(handler) = &self.notice_handler {
+            handler.0.notice(notice);
+        } else {
+            #[cfg(feature = "log")]
+            log::warn!("{notice}");
+        }
+    }
+
+    fn notification(&mut self, body: bytes::Bytes) -> Result<()> {
+        let notification = backend::NotificationResponse::decode(
+            backend::NotificationResponse::MSGTYPE,
+            body,
+        )?;
+        verbose!(?notification,"notification");
+        self.notifications.push_back(notification.into());
+        Ok(())
+    }
+}
+
+unit_error! {
+    /// None of the hosts satisfy the [`TargetSessionAttrs`].
+    pub struct SessionAttrsMismatch("no server satisfy the target_session_attrs");
+}
+
+/// Parse `server_version` parameter into `server_version_num` format.
+///
+/// The value may contains suffix, e.g. `16beta1` or `15.4 (Debian 15.4-1.pgdg120+1)`.
+fn parse_server_version(version: &str) -> Option<u32> {
+    let end = version.find(|e: char|!e.is_ascii_digit() && e != '.').unwrap_or(version.len());
+    let mut parts = version[..end].split('.').map(str::parse::<u32>);
+
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().and_then(Result::ok).unwrap_or(0);
+
+    // since version 10, there is only major and minor version
+    if major >= 10 {
+        return Some(major * 10000 + minor);
+    }
+
+    let patch = parts.next().and_then(Result::ok).unwrap_or(0);
+    Some(major * 10000 + minor * 100 + patch)
 }
 
 impl Connection {
@@ -168,7 +530,9 @@ macro_rules! poll_message {
     ) => {
         let Some(mut header) = $io.read_buf.get(..5) else {
             $io.read_buf.reserve(1024);
-            ready!(crate::io::poll_read(&mut $io.socket, &mut $io.read_buf, $cx)?);
+            if ready!(crate::io::poll_read(&mut $io.socket, &mut $io.read_buf, $cx)?) == 0 {
+                Err(io::Error::from(io::ErrorKind::UnexpectedEof))?
+            }
             continue;
         };
 
@@ -177,7 +541,9 @@ macro_rules! poll_message {
 
         if $io.read_buf.len() - 1/*msgtype*/ < len {
             $io.read_buf.reserve(1 + len);
-            ready!(crate::io::poll_read(&mut $io.socket, &mut $io.read_buf, $cx)?);
+            if ready!(crate::io::poll_read(&mut $io.socket, &mut $io.read_buf, $cx)?) == 0 {
+                Err(io::Error::from(io::ErrorKind::UnexpectedEof))?
+            }
             continue;
         }
 
@@ -198,51 +564,49 @@ impl Connection {
     pub fn ready(&mut self) -> impl Future<Output = Result<()>> {
         std::future::poll_fn(|cx|self.poll_ready(cx))
     }
+}
 
-    /// Attempt to execute all queued action.
-    ///
-    /// See the struct module for [more details][1].
+impl Connection {
+    /// Attempt to receive a notification.
     ///
-    /// [1]: Connection#pending-messages
-    pub(crate) fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<()>> {
-        if !self.write_buf.is_empty() {
-            ready!(self.poll_flush(cx)?)
-        }
+    /// Queued actions are executed first.
+    pub(crate) fn poll_notification(&mut self, cx: &mut Context) -> Poll<Result<Notification>> {
+        ready!(self.poll_ready(cx)?);
 
-        while self.sync_pending != 0 {
-            verbose!(self.sync_pending,"healthcheck");
+        loop {
+            if let Some(notification) = self.notifications.pop_front() {
+                return Poll::Ready(Ok(notification));
+            }
 
             poll_message! {
                 poll(self, cx);
                 let msgtype;
-                let _body;
+                let body;
             }
 
             match msgtype {
-                ErrorResponse::MSGTYPE => {
-                    self.send(frontend::Sync);
-                    // NOTE:
-                    // not documented but the `Sync` will get
-                    // eaten by ErrorResponse based on currently happening
-                    self.ready_request();
-                    #[cfg(feature = "log")]
-                    log::error!("{}",ErrorResponse::new(_body));
-                },
+                backend::NotificationResponse::MSGTYPE => {
+                    self.notification(body)?;
+                }
                 NoticeResponse::MSGTYPE => {
-                    #[cfg(feature = "log")]
-                    log::warn!("{}",NoticeResponse::new(_body));
+                    self.notice(body);
                 },
                 backend::ParameterStatus::MSGTYPE => {
-                    // currently, we dont care about parameter status
+                    self.parameter_status(body)?;
                 }
-                backend::ReadyForQuery::MSGTYPE => {
-                    self.sync_pending -= 1;
-                },
-                _ => {} // ignore all messages until `ReadyForQuery` received
+                // e.g. the server is shutting down
+                ErrorResponse::MSGTYPE => Err(ErrorResponse::new(body))?,
+                _ => Err(backend::BackendMessage::decode(msgtype, body)?.unexpected("waiting notification"))?,
             }
         }
+    }
 
-        Poll::Ready(Ok(()))
+    /// Execute a command without result rows via simple query protocol.
+    pub(crate) async fn simple_command(&mut self, sql: &str) -> Result<()> {
+        self.send(frontend::Query { sql });
+        self.recv::<backend::CommandComplete>().await?;
+        self.recv::<backend::ReadyForQuery>().await?;
+        Ok(())
     }
 }
 
@@ -263,18 +627,27 @@ impl PgTransport for Connection {
 
             match msgtype {
                 ErrorResponse::MSGTYPE => {
-                    self.send(frontend::Sync);
+                    // backend discards messages until `Sync`, which may already be sent
+                    if self.syncs == 0 {
+                        self.send(frontend::Sync);
+                    }
                     self.ready_request();
                     Err(ErrorResponse::new(body))?
                 },
                 NoticeResponse::MSGTYPE => {
-                    #[cfg(feature = "log")]
-                    log::warn!("{}",NoticeResponse::new(body));
+                    self.notice(body);
                     continue;
                 },
                 backend::ParameterStatus::MSGTYPE => {
-                    // currently, we dont care about parameter status
+                    self.parameter_status(body)?;
                 }
+                backend::NotificationResponse::MSGTYPE => {
+                    self.notification(body)?;
+                }
+                backend::ReadyForQuery::MSGTYPE => {
+                    self.syncs = self.syncs.saturating_sub(1);
+                    return Poll::Ready(Ok(B::decode(msgtype, body)?));
+                },
                 _ => return Poll::Ready(Ok(B::decode(msgtype, body)?)),
             }
         }
@@ -284,8 +657,87 @@ impl PgTransport for Connection {
         self.sync_pending += 1;
     }
 
+    /// Attempt to execute all queued action.
+    ///
+    /// See the struct module for [more details][1].
+    ///
+    /// [1]: Connection#pending-messages
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        loop {
+            if !self.write_buf.is_empty() {
+                ready!(self.poll_flush(cx)?)
+            }
+
+            if self.sync_pending == 0 {
+                break;
+            }
+
+            verbose!(self.sync_pending,"healthcheck");
+
+            poll_message! {
+                poll(self, cx);
+                let msgtype;
+                let body;
+            }
+
+            match msgtype {
+                ErrorResponse::MSGTYPE => {
+                    #[cfg(feature = "log")]
+                    log::error!("{}",ErrorResponse::new(body));
+                    // backend discards messages until `Sync`, which may already be sent
+                    if self.syncs != 0 {
+                        continue;
+                    }
+                    self.send(frontend::Sync);
+                    self.ready_request();
+                },
+                NoticeResponse::MSGTYPE => {
+                    self.notice(body);
+                },
+                backend::ParameterStatus::MSGTYPE => {
+                    self.parameter_status(body)?;
+                }
+                backend::NotificationResponse::MSGTYPE => {
+                    self.notification(body)?;
+                }
+                backend::ReadyForQuery::MSGTYPE => {
+                    self.sync_pending -= 1;
+                    self.syncs = self.syncs.saturating_sub(1);
+                },
+                _ => {} // ignore all messages until `ReadyForQuery` received
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn cancel_token(&self) -> Option<CancelToken> {
+        Some(self.cancel_token.clone())
+    }
+
+    fn query_timeout(&self) -> Option<Duration> {
+        self.query_timeout
+    }
+
+    fn capture_notices(&mut self) {
+        self.captured_notices.get_or_insert_default();
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.captured_notices.take().unwrap_or_default()
+    }
+
+    fn release_notices(&mut self) {
+        for notice in self.take_notices() {
+            self.handle_notice(&notice);
+        }
+    }
+
     fn send<F: FrontendProtocol>(&mut self, message: F) {
         verbose!(?message,"(F)");
+        if F::MSGTYPE == frontend::Sync::MSGTYPE || F::MSGTYPE == frontend::Query::MSGTYPE {
+            self.syncs += 1;
+        }
         frontend::write(message, &mut self.write_buf);
     }
 
@@ -294,19 +746,34 @@ impl PgTransport for Connection {
         startup.write(&mut self.write_buf);
     }
 
-    fn get_stmt(&mut self, sqlid: u64) -> Option<StatementName> {
-        self.stmts.get(&sqlid).cloned().inspect(|_name|{
-            span!("statement");
-            verbose!(name=%_name,"cache hit")
-        })
+    fn get_stmt(&mut self, sql: &str) -> Option<StatementName> {
+        let stmt = self.stmts.as_mut()?.get(sql).cloned();
+        match &stmt {
+            Some(_name) => {
+                span!("statement");
+                verbose!(name=%_name,"cache hit");
+                self.stmt_cache_hits += 1;
+            },
+            None => self.stmt_cache_misses += 1,
+        }
+        stmt
+    }
+
+    fn next_stmt(&mut self) -> Option<StatementName> {
+        self.stmts.as_ref()?;
+        Some(StatementName::next(&mut self.stmt_id))
     }
 
-    fn add_stmt(&mut self, id: u64, name: StatementName) {
+    fn add_stmt(&mut self, sql: &str, name: StatementName) {
+        let Some(stmts) = &mut self.stmts else {
+            return;
+        };
+
         span!("statement");
 
         verbose!(%name,"added");
 
-        if let Some((_id,name)) = self.stmts.push(id, name) {
+        if let Some((_sql,name)) = stmts.push(sql.to_owned(), name) {
             verbose!(%name,"removed");
 
             self.send(frontend::Close {
@@ -329,3 +796,297 @@ impl Executor for Connection {
     }
 }
 
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{TargetSessionAttrs, parse_server_version, session_attrs};
+    use crate::transport::test::{ScriptedTransport, block_on};
+
+    #[test]
+    fn server_version() {
+        assert_eq!(parse_server_version("15.4 (Debian 15.4-1.pgdg120+1)"), Some(150004));
+        assert_eq!(parse_server_version("16beta1"), Some(160000));
+        assert_eq!(parse_server_version("9.6.2"), Some(90602));
+        assert_eq!(parse_server_version("devel"), None);
+    }
+
+    #[test]
+    fn session_attrs_decision() {
+        use TargetSessionAttrs::*;
+
+        // (attrs, default_transaction_read_only, in_hot_standby, expected)
+        let table = [
+            (ReadWrite, Some(false), Some(false), true),
+            (ReadWrite, Some(true), Some(false), false),
+            (ReadWrite, Some(false), Some(true), false),
+            (ReadWrite, Some(false), None, true),
+            (ReadWrite, None, Some(true), false),
+            (ReadOnly, Some(false), Some(false), false),
+            (ReadOnly, Some(true), Some(false), true),
+            (ReadOnly, Some(false), Some(true), true),
+            (ReadOnly, None, Some(false), false),
+            (Primary, Some(true), Some(false), true),
+            (Primary, Some(false), Some(true), false),
+            (Standby, Some(true), Some(false), false),
+            (Standby, Some(false), Some(true), true),
+            (Any, Some(true), Some(true), true),
+            (PreferStandby, Some(false), Some(false), true),
+        ];
+
+        for (attrs, read_only, hot_standby, expected) in table {
+            let mut io = ScriptedTransport::new();
+            let matched = block_on(session_attrs(&mut io, attrs, read_only, hot_standby)).unwrap();
+            assert_eq!(matched, expected, "{attrs:?} {read_only:?} {hot_standby:?}");
+            assert!(io.sent().is_empty(), "{attrs:?} {read_only:?} {hot_standby:?}");
+        }
+    }
+
+    #[test]
+    fn session_attrs_query() {
+        use TargetSessionAttrs::*;
+
+        // (attrs, default_transaction_read_only, query result, expected)
+        let table = [
+            (ReadWrite, "off", true),
+            (ReadWrite, "on", false),
+            (ReadOnly, "on", true),
+            (Primary, "false", true),
+            (Primary, "true", false),
+            (Standby, "true", true),
+        ];
+
+        for (attrs, value, expected) in table {
+            let mut io = ScriptedTransport::new();
+            io.push_query(value);
+            let matched = block_on(session_attrs(&mut io, attrs, None, None)).unwrap();
+            assert_eq!(matched, expected, "{attrs:?} {value}");
+
+            let (msgtype, body) = io.sent().remove(0);
+            assert_eq!(msgtype, b'P');
+            let sql = match attrs {
+                ReadWrite | ReadOnly => "SHOW transaction_read_only",
+                _ => "SELECT pg_catalog.pg_is_in_recovery()::text",
+            };
+            assert!(body.windows(sql.len()).any(|e| e == sql.as_bytes()));
+        }
+    }
+
+    /// Frontend messages type and body received by [`fake_server`].
+    #[cfg(feature = "tokio")]
+    pub(crate) type Received = Vec<(u8, Vec<u8>)>;
+
+    /// A fake backend serving `connections` connections in sequence, returns its port and all
+    /// received frontend messages.
+    ///
+    /// Each query returns its sql as a single `text` row, except if it starts with `ERROR`, the
+    /// query fails at `Execute`. A query containing `NOTICE` also send its sql as a notice, a query
+    /// `NOTIFY <channel>` also send a notification before the row, and the connection is closed
+    /// after a `SHUTDOWN` query. Simple query returns no rows.
+    ///
+    /// A query starting with `SLEEP` waits for a `CancelRequest` at `Execute` then fails as
+    /// cancelled, a query starting with `SLOW` waits for a `CancelRequest` at `Parse` then
+    /// completes normally. The `CancelRequest` is received on another connection, and recorded
+    /// with message type `0`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn fake_server(connections: usize) -> (u16, std::thread::JoinHandle<Received>) {
+        use std::io::{Read, Write};
+        use crate::transport::test::{text_column, text_row};
+
+        fn message(msgtype: u8, body: &[u8]) -> Vec<u8> {
+            [&[msgtype][..], &(body.len() as u32 + 4).to_be_bytes(), body].concat()
+        }
+
+        fn cancelled(listener: &std::net::TcpListener, received: &mut Received) {
+            let mut stream = listener.accept().unwrap().0;
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            let mut body = vec![0; u32::from_be_bytes(len) as usize - 4];
+            stream.read_exact(&mut body).unwrap();
+            received.push((0, body));
+        }
+
+        fn serve(mut stream: std::net::TcpStream, listener: &std::net::TcpListener, received: &mut Received) {
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).unwrap();
+            stream.read_exact(&mut vec![0; u32::from_be_bytes(len) as usize - 4]).unwrap();
+            stream.write_all(&message(b'R', &0u32.to_be_bytes())).unwrap();
+            stream.write_all(&message(b'K', &[0, 0, 0, 42, 0, 0, 0, 7])).unwrap();
+            stream.write_all(&message(b'Z', b"I")).unwrap();
+
+            let mut stmts = std::collections::HashMap::new();
+            let mut sql = String::new();
+            let mut failed = false;
+            loop {
+                let mut header = [0u8; 5];
+                if stream.read_exact(&mut header).is_err() {
+                    break;
+                }
+                let mut body = vec![0; u32::from_be_bytes(header[1..].try_into().unwrap()) as usize - 4];
+                stream.read_exact(&mut body).unwrap();
+
+                let reply = match header[0] {
+                    b'S' if sql == "SHUTDOWN" => {
+                        stream.write_all(&message(b'Z', b"I")).unwrap();
+                        received.push((header[0], body));
+                        break;
+                    },
+                    b'S' => {
+                        failed = false;
+                        message(b'Z', b"I")
+                    },
+                    b'X' => {
+                        received.push((header[0], body));
+                        break;
+                    },
+                    _ if failed => vec![],
+                    b'Q' => {
+                        let tag = body.split(|&e|e == b' ' || e == 0).next().unwrap();
+                        [message(b'C', &[tag, b"\0"].concat()), message(b'Z', b"I")].concat()
+                    },
+                    b'P' => {
+                        let mut fields = body.split(|&e|e == 0);
+                        let name = fields.next().unwrap().to_vec();
+                        sql = String::from_utf8(fields.next().unwrap().to_vec()).unwrap();
+                        stmts.insert(name, sql.clone());
+                        if sql.starts_with("SLOW") {
+                            cancelled(listener, received);
+                        }
+                        message(b'1', b"")
+                    },
+                    b'B' => {
+                        let name = body.split(|&e|e == 0).nth(1).unwrap();
+                        sql = stmts[name].clone();
+                        message(b'2', b"")
+                    },
+                    b'C' => {
+                        stmts.remove(&body[1..body.len() - 1]);
+                        message(b'3', b"")
+                    },
+                    b'D' => message(b'T', &text_column()),
+                    b'E' => {
+                        let mut reply = vec![];
+                        if sql.contains("NOTICE") {
+                            reply.extend(message(b'N', format!("SNOTICE\0C00000\0M{sql}\0\0").as_bytes()));
+                        }
+                        let error: Option<&[u8]> = if sql.starts_with("SLEEP") {
+                            cancelled(listener, received);
+                            Some(b"SERROR\0C57014\0Mcanceling statement due to user request\0\0")
+                        } else if sql.starts_with("ERROR") {
+                            Some(b"SERROR\0C42000\0Mfailed\0\0")
+                        } else {
+                            None
+                        };
+                        if let Some(error) = error {
+                            failed = true;
+                            reply.extend(message(b'E', error));
+                            stream.write_all(&reply).unwrap();
+                            received.push((header[0], body));
+                            continue;
+                        }
+                        if let Some(channel) = sql.strip_prefix("NOTIFY ") {
+                            let body = [&[0, 0, 0, 42][..], channel.as_bytes(), b"\0\0"].concat();
+                            reply.extend(message(b'A', &body));
+                        }
+                        reply.extend(message(b'D', &text_row(&sql)));
+                        reply.extend(message(b'C', b"SELECT 1\0"));
+                        reply
+                    },
+                    _ => vec![],
+                };
+                received.push((header[0], body));
+                stream.write_all(&reply).unwrap();
+            }
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let mut received = vec![];
+            for _ in 0..connections {
+                serve(listener.accept().unwrap().0, &listener, &mut received);
+            }
+            received
+        });
+
+        (port, server)
+    }
+
+    /// Returns the config to connect to [`fake_server`].
+    #[cfg(feature = "tokio")]
+    pub(crate) fn fake_config(port: u16) -> super::Config {
+        super::Config::new()
+            .with_host("127.0.0.1")
+            .with_port(port)
+            .with_ssl_mode(super::SslMode::Disable)
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn error_single_sync() {
+        let (port, server) = fake_server(1);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut conn = super::Connection::connect_with(fake_config(port)).await.unwrap();
+
+            let result: crate::Result<String> = crate::query_scalar("ERROR", &mut conn).fetch_one().await;
+            assert!(result.is_err());
+
+            let value: String = crate::query_scalar("SELECT 'ok'", &mut conn).fetch_one().await.unwrap();
+            assert_eq!(value, "SELECT 'ok'");
+            conn.close().await.unwrap();
+        });
+
+        // a `Sync` for each query, the backend discard messages until the `Sync` on error
+        let received = server.join().unwrap();
+        assert_eq!(received.iter().filter(|(msgtype,_)|*msgtype == b'S').count(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn statement_cache() {
+        let (port, server) = fake_server(2);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let config = fake_config(port).with_statement_cache_size(0);
+            let mut conn = super::Connection::connect_with(config).await.unwrap();
+            for _ in 0..2 {
+                let _: String = crate::query_scalar("SELECT 1", &mut conn).fetch_one().await.unwrap();
+            }
+            assert_eq!(conn.statement_cache_len(), 0);
+            assert_eq!(conn.statement_cache_hits(), 0);
+            assert_eq!(conn.statement_cache_misses(), 0);
+            conn.close().await.unwrap();
+
+            let config = fake_config(port).with_statement_cache_size(2);
+            let mut conn = super::Connection::connect_with(config).await.unwrap();
+            // hit requires exactly the same sql, only leading and trailing whitespace is trimmed
+            for sql in ["SELECT 1", " SELECT 1\n", "select 1", "SELECT  1", "select 1"] {
+                let _: String = crate::query_scalar(sql, &mut conn).fetch_one().await.unwrap();
+            }
+            assert_eq!(conn.statement_cache_hits(), 2);
+            assert_eq!(conn.statement_cache_misses(), 3);
+            assert_eq!(conn.statement_cache_len(), 2);
+
+            conn.clear_statement_cache();
+            conn.ready().await.unwrap();
+            assert_eq!(conn.statement_cache_len(), 0);
+            conn.close().await.unwrap();
+        });
+
+        let received = server.join().unwrap();
+        let split = received.iter().position(|(msgtype,_)|*msgtype == b'X').unwrap();
+        let (disabled, cached) = received.split_at(split);
+
+        let parsed = |received: &[(u8, Vec<u8>)]| received
+            .iter()
+            .filter(|(msgtype,_)|*msgtype == b'P')
+            .map(|(_,body)|String::from_utf8(body.split(|&e|e == 0).next().unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>();
+
+        // cache disabled use the unnamed statement
+        assert_eq!(parsed(disabled), ["", ""]);
+        // `SELECT 1` is evicted after `SELECT  1` is prepared
+        assert_eq!(parsed(cached), ["q1", "q2", "q3"]);
+        let closed = cached.iter().filter(|(msgtype,_)|*msgtype == b'C').count();
+        assert_eq!(closed, 3);
+    }
+}
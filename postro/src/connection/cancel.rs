@@ -0,0 +1,135 @@
+//! Query cancellation.
+//!
+//! <https://www.postgresql.org/docs/current/protocol-flow.html#PROTOCOL-FLOW-CANCELING-REQUESTS>
+use bytes::BytesMut;
+use std::sync::Arc;
+
+use super::{Config, Connection};
+use crate::{
+    Result,
+    common::ByteStr,
+    net,
+    postgres::{backend::BackendKeyData, frontend},
+};
+
+/// A token to cancel the query in progress of a [`Connection`].
+///
+/// The token is retrieved via [`Connection::cancel_token`] or [`PoolConnection::cancel_token`][1],
+/// it can be cloned and sent to another task, while the connection is busy.
+///
+/// Cancellation is sent over a new connection to the same server, with the same SSL settings.
+///
+/// Note that cancellation is best effort, the server may have already finished the query, in which
+/// case nothing happens. If cancellation is effective, the query fails with `query_canceled` error.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn app() -> postro::Result<()> {
+/// let mut conn = postro::Connection::connect_env().await?;
+/// let token = conn.cancel_token();
+///
+/// tokio::spawn(async move {
+///     tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+///     token.cancel().await
+/// });
+///
+/// let result = postro::query("SELECT pg_sleep(60)", &mut conn).execute().await;
+/// assert!(result.is_err());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [1]: crate::pool::PoolConnection::cancel_token
+#[derive(Clone, Debug)]
+pub struct CancelToken {
+    pub(crate) config: Arc<Config>,
+    pub(crate) host: ByteStr,
+    pub(crate) addr: Option<ByteStr>,
+    pub(crate) port: u16,
+    pub(crate) tls: bool,
+    pub(crate) backend_key: BackendKeyData,
+}
+
+impl CancelToken {
+    /// Request the server to cancel the query in progress.
+    ///
+    /// Returns when the server closed the cancel connection, the `connect_timeout` is applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tokio` feature is not enabled.
+    pub async fn cancel(&self) -> Result<()> {
+        net::timeout(self.config.connect_timeout, self.send()).await?
+    }
+
+    async fn send(&self) -> Result<()> {
+        let mut socket = Connection::connect_socket(&self.config, &self.host, self.addr.as_deref(), self.port).await?;
+        if self.tls {
+            socket = net::tls::negotiate(socket, &self.config, &self.host).await?;
+        }
+
+        let mut buf = BytesMut::with_capacity(12 + self.backend_key.secret_key.len());
+        frontend::CancelRequest {
+            process_id: self.backend_key.process_id,
+            secret_key: &self.backend_key.secret_key,
+        }
+        .write(&mut buf);
+        std::future::poll_fn(|cx|crate::io::poll_write_all(&mut socket, &mut buf, cx)).await?;
+
+        // the server does not reply, it closes the connection after processing the request
+        loop {
+            buf.clear();
+            buf.reserve(64);
+            if std::future::poll_fn(|cx|crate::io::poll_read(&mut socket, &mut buf, cx)).await? == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use futures_core::Stream;
+    use std::{pin::Pin, time::Duration};
+
+    use crate::{
+        Connection,
+        connection::test::{fake_config, fake_server},
+        error::ErrorKind,
+    };
+
+    #[test]
+    fn cancel() {
+        let (port, server) = fake_server(1);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut conn = Connection::connect_with(fake_config(port)).await.unwrap();
+            let token = conn.cancel_token();
+
+            let cancel = tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                token.cancel().await
+            });
+
+            let mut stream = crate::query_scalar::<_, _, String>("SLEEP", &mut conn).fetch();
+            let result = std::future::poll_fn(|cx|Pin::new(&mut stream).poll_next(cx)).await.unwrap();
+            let err = result.unwrap_err();
+            let ErrorKind::Database(err) = err.kind() else {
+                panic!("expected database error");
+            };
+            assert_eq!(err.code(), "57014");
+            cancel.await.unwrap().unwrap();
+
+            let value: String = crate::query_scalar("SELECT 'ok'", &mut conn).fetch_one().await.unwrap();
+            assert_eq!(value, "SELECT 'ok'");
+        });
+
+        // the `CancelRequest` is received on another connection, with the backend key of `fake_server`
+        let received = server.join().unwrap();
+        let (_, body) = received.iter().find(|(msgtype,_)|*msgtype == 0).unwrap();
+        assert_eq!(body, &[&80_877_102u32.to_be_bytes()[..], &[0, 0, 0, 42], &[0, 0, 0, 7]].concat());
+    }
+}
@@ -0,0 +1,83 @@
+//! Dynamic authentication credential.
+use std::{error::Error, fmt, pin::Pin, sync::Arc};
+
+/// Future returned by [`CredentialProvider::password`].
+pub type CredentialFuture<'a> = Pin<Box<dyn Future<Output = Result<String, CredentialError>> + Send + 'a>>;
+
+/// A source of authentication password that is resolved on each new connection.
+///
+/// This allows rotated password or short-lived token to be used without rebuilding
+/// [`Config`][1] or [`Pool`][2].
+///
+/// The provider is only called when the server request password authentication.
+///
+/// Any `Fn() -> impl Future<Output = Result<String, E>>` implements this trait.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn app() -> postro::Result<()> {
+/// # async fn fetch_token() -> std::io::Result<String> { todo!() }
+/// let config = postro::Config::from_env().with_credential_provider(|| fetch_token());
+///
+/// let conn = postro::Connection::connect_with(config).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [1]: crate::Config
+/// [2]: crate::Pool
+pub trait CredentialProvider: Send + Sync + 'static {
+    /// Returns the password or token to authenticate with.
+    fn password(&self) -> CredentialFuture<'_>;
+}
+
+impl<F, Fut, E> CredentialProvider for F
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, E>> + Send + 'static,
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn password(&self) -> CredentialFuture<'_> {
+        let future = self();
+        Box::pin(async move { future.await.map_err(|err|CredentialError(err.into())) })
+    }
+}
+
+/// Shared [`CredentialProvider`].
+#[derive(Clone)]
+pub(crate) struct Credential(pub(crate) Arc<dyn CredentialProvider>);
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CredentialProvider")
+    }
+}
+
+/// An error when [`CredentialProvider`] failed to provide password.
+pub struct CredentialError(Box<dyn Error + Send + Sync>);
+
+impl CredentialError {
+    /// Create new error from any error type.
+    pub fn new(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self(err.into())
+    }
+}
+
+impl Error for CredentialError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to retrieve credential: {}", self.0)
+    }
+}
+
+impl fmt::Debug for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
@@ -0,0 +1,46 @@
+//! Notice handling.
+use std::{fmt, sync::Arc};
+
+use crate::postgres::NoticeResponse;
+
+/// A callback which receives `NoticeResponse` sent by the server, e.g. from `RAISE NOTICE`.
+///
+/// Without a handler, notices are logged if the `log` feature is enabled, otherwise ignored.
+///
+/// Any `Fn(&NoticeResponse)` implements this trait.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn app() -> postro::Result<()> {
+/// let config = postro::Config::from_env().with_notice_handler(|notice: &postro::postgres::NoticeResponse| {
+///     eprintln!("{}: {}", notice.severity(), notice.message());
+/// });
+///
+/// let conn = postro::Connection::connect_with(config).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait NoticeHandler: Send + Sync + 'static {
+    /// Handle the notice.
+    fn notice(&self, notice: &NoticeResponse);
+}
+
+impl<F> NoticeHandler for F
+where
+    F: Fn(&NoticeResponse) + Send + Sync + 'static,
+{
+    fn notice(&self, notice: &NoticeResponse) {
+        self(notice)
+    }
+}
+
+/// Shared [`NoticeHandler`].
+#[derive(Clone)]
+pub(crate) struct NoticeCallback(pub(crate) Arc<dyn NoticeHandler>);
+
+impl fmt::Debug for NoticeCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NoticeHandler")
+    }
+}
@@ -0,0 +1,164 @@
+//! Asynchronous notification.
+//!
+//! <https://www.postgresql.org/docs/current/protocol-flow.html#PROTOCOL-ASYNC>
+use futures_core::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use super::Connection;
+use crate::{
+    Result,
+    common::ByteStr,
+    postgres::backend,
+};
+
+/// A notification raised by `NOTIFY` command.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    process_id: u32,
+    channel: ByteStr,
+    payload: ByteStr,
+}
+
+impl Notification {
+    /// Returns the process ID of the notifying backend process.
+    pub fn process_id(&self) -> u32 {
+        self.process_id
+    }
+
+    /// Returns the name of the channel that the notify has been raised on.
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Returns the payload string passed from the notifying process, empty if not specified.
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+}
+
+impl From<backend::NotificationResponse> for Notification {
+    fn from(value: backend::NotificationResponse) -> Self {
+        Self {
+            process_id: value.process_id,
+            channel: value.channel,
+            payload: value.payload,
+        }
+    }
+}
+
+/// A [`Stream`] of notifications received by the [`Connection`].
+///
+/// Returned from [`Connection::notifications`].
+///
+/// The stream ends after returning an error, e.g. the connection is closed, as the connection is
+/// no longer usable.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct Notifications<'a> {
+    conn: &'a mut Connection,
+    terminated: bool,
+}
+
+impl Stream for Notifications<'_> {
+    type Item = Result<Notification>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        if me.terminated {
+            return Poll::Ready(None);
+        }
+        let result = ready!(me.conn.poll_notification(cx));
+        me.terminated = result.is_err();
+        Poll::Ready(Some(result))
+    }
+}
+
+impl Connection {
+    /// Start listening for notification on `channel`.
+    ///
+    /// Notifications are received via [`Connection::notifications`].
+    pub async fn listen(&mut self, channel: &str) -> Result<()> {
+        self.simple_command(&format!("LISTEN {}", quote_ident(channel))).await
+    }
+
+    /// Stop listening for notification on `channel`.
+    pub async fn unlisten(&mut self, channel: &str) -> Result<()> {
+        self.simple_command(&format!("UNLISTEN {}", quote_ident(channel))).await
+    }
+
+    /// Stop listening for notification on all channels.
+    pub async fn unlisten_all(&mut self) -> Result<()> {
+        self.simple_command("UNLISTEN *").await
+    }
+
+    /// Returns a [`Stream`] of notifications from listened channels.
+    ///
+    /// See [`Connection::recv_notification`] for more details.
+    pub fn notifications(&mut self) -> Notifications<'_> {
+        Notifications { conn: self, terminated: false }
+    }
+
+    /// Receive a notification from listened channels.
+    ///
+    /// Notifications received while a query is in progress are buffered, and returned first.
+    ///
+    /// Note that the buffer is unbounded, notifications should be consumed while listening.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn app(mut conn: postro::Connection) -> postro::Result<()> {
+    /// conn.listen("jobs").await?;
+    ///
+    /// loop {
+    ///     let notification = conn.recv_notification().await?;
+    ///     println!("{}: {}", notification.channel(), notification.payload());
+    /// }
+    /// # }
+    /// ```
+    pub fn recv_notification(&mut self) -> impl Future<Output = Result<Notification>> {
+        std::future::poll_fn(|cx|self.poll_notification(cx))
+    }
+}
+
+/// Quote `ident` as SQL identifier.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use futures_core::Stream;
+    use std::pin::Pin;
+
+    use crate::{Connection, connection::test::{fake_config, fake_server}};
+
+    #[test]
+    fn buffered_notification() {
+        let (port, server) = fake_server(1);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut conn = Connection::connect_with(fake_config(port)).await.unwrap();
+
+            // notification received while the query is in progress
+            let value: String = crate::query_scalar("NOTIFY jobs", &mut conn).fetch_one().await.unwrap();
+            assert_eq!(value, "NOTIFY jobs");
+
+            let notification = conn.recv_notification().await.unwrap();
+            assert_eq!(notification.process_id(), 42);
+            assert_eq!(notification.channel(), "jobs");
+            assert_eq!(notification.payload(), "");
+
+            // the stream ends after the connection is closed
+            let _: String = crate::query_scalar("SHUTDOWN", &mut conn).fetch_one().await.unwrap();
+            let mut notifications = conn.notifications();
+            let mut notifications = Pin::new(&mut notifications);
+            assert!(std::future::poll_fn(|cx|notifications.as_mut().poll_next(cx)).await.unwrap().is_err());
+            assert!(std::future::poll_fn(|cx|notifications.as_mut().poll_next(cx)).await.is_none());
+        });
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Password file, `~/.pgpass`.
+//!
+//! <https://www.postgresql.org/docs/current/libpq-pgpass.html>
+use std::{fs, path::PathBuf};
+
+use super::Config;
+
+/// Look up password for given config and host from the password file.
+///
+/// The file is `passfile` parameter or `PGPASSFILE` if set, otherwise `~/.pgpass`.
+///
+/// Returns `None` if the file does not exist, cannot be read, have permissions
+/// that are too open, or no entry matches.
+pub fn lookup(config: &Config, host: &str, port: u16) -> Option<String> {
+    let path = match config.passfile.as_deref() {
+        Some(path) => PathBuf::from(path),
+        None => default_path()?,
+    };
+
+    let meta = fs::metadata(&path).ok()?;
+    if !meta.is_file() {
+        return None;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if meta.permissions().mode() & 0o077 != 0 {
+            #[cfg(feature = "log")]
+            log::warn!(
+                "password file {path:?} has group or world access; \
+                permissions should be u=rw (0600) or less"
+            );
+            return None;
+        }
+    }
+
+    let content = fs::read_to_string(&path).ok()?;
+
+    // a host name of `localhost` is also searched for when the connection is a Unix-domain socket
+    let host = match host.starts_with('/') {
+        true => "localhost",
+        false => host,
+    };
+
+    find(&content, host, &port.to_string(), config.dbname(), &config.user)
+}
+
+/// Find the first matching entry, in format of `hostname:port:database:username:password`.
+fn find(content: &str, host: &str, port: &str, dbname: &str, user: &str) -> Option<String> {
+    content
+        .lines()
+        .filter(|line|!line.starts_with('#'))
+        .find_map(|line| {
+            let [entry_host, entry_port, entry_dbname, entry_user, password] = split(line)?;
+            let matches = |field: &str, value: &str| field == "*" || field == value;
+
+            (matches(&entry_host, host)
+                && matches(&entry_port, port)
+                && matches(&entry_dbname, dbname)
+                && matches(&entry_user, user))
+            .then_some(password)
+        })
+}
+
+/// Split entry into its fields, handling `\:` and `\\` escapes.
+///
+/// Returns `None` if entry have less than 5 fields.
+fn split(line: &str) -> Option<[String; 5]> {
+    let mut fields: [String; 5] = Default::default();
+    let mut index = 0;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => fields[index].extend(chars.next()),
+            // the rest of line after the fourth colon is the password
+            ':' if index < 4 => index += 1,
+            ch => fields[index].push(ch),
+        }
+    }
+
+    (index == 4).then_some(fields)
+}
+
+fn default_path() -> Option<PathBuf> {
+    #[cfg(windows)]
+    {
+        std::env::var_os("APPDATA").map(|e|PathBuf::from(e).join("postgresql").join("pgpass.conf"))
+    }
+
+    #[cfg(not(windows))]
+    {
+        std::env::home_dir().map(|home|home.join(".pgpass"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::find;
+
+    #[test]
+    fn pgpass() {
+        let content = "\
+            # comment:*:*:*:nope\n\
+            db.example.com:5432:app:alice:secret\n\
+            *:5433:*:bob:pa\\:ss\\\\word\n\
+            my\\:host:*:*:*:colon:in:password\n\
+            incomplete:entry\n\
+            *:*:*:*:fallback\n\
+        ";
+
+        assert_eq!(find(content, "db.example.com", "5432", "app", "alice").as_deref(), Some("secret"));
+        assert_eq!(find(content, "localhost", "5433", "any", "bob").as_deref(), Some("pa:ss\\word"));
+        assert_eq!(find(content, "my:host", "5432", "app", "carol").as_deref(), Some("colon:in:password"));
+        assert_eq!(find(content, "db.example.com", "5432", "app", "bob").as_deref(), Some("fallback"));
+        assert_eq!(find(content, "incomplete", "5432", "app", "dave").as_deref(), Some("fallback"));
+        assert_eq!(find("", "localhost", "5432", "app", "dave"), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! Connection service file, `pg_service.conf`.
+//!
+//! <https://www.postgresql.org/docs/current/libpq-pgservice.html>
+use std::{env::var_os, fs, path::PathBuf};
+
+use super::ParseError;
+
+/// Look up connection parameters of given service name.
+///
+/// The per-user service file, `PGSERVICEFILE` or `~/.pg_service.conf`, is searched first.
+/// If the service is not found, the system-wide file `PGSYSCONFDIR/pg_service.conf` is searched.
+pub fn lookup(name: &str) -> Result<Vec<(String, String)>, ParseError> {
+    let user_file = match var_os("PGSERVICEFILE") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::home_dir().map(|home|home.join(".pg_service.conf")),
+    };
+    let system_file = var_os("PGSYSCONFDIR").map(|dir|PathBuf::from(dir).join("pg_service.conf"));
+
+    for path in [user_file, system_file].into_iter().flatten() {
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Some(params) = find(&content, name)
+            .map_err(|reason|ParseError { reason: format!("{reason} in service file {path:?}").into() })?
+        {
+            return Ok(params);
+        }
+    }
+
+    Err(ParseError { reason: format!("definition of service `{name}` not found").into() })
+}
+
+/// Find service section, returns its `key=value` parameters.
+fn find(content: &str, name: &str) -> Result<Option<Vec<(String, String)>>, String> {
+    let mut params = None::<Vec<(String, String)>>;
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[') {
+            // the section is ended by the next section
+            if params.is_some() {
+                break;
+            }
+            if section.strip_suffix(']') == Some(name) {
+                params = Some(vec![]);
+            }
+            continue;
+        }
+
+        let Some(params) = params.as_mut() else {
+            continue;
+        };
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("syntax error at line {}", i + 1));
+        };
+
+        let key = key.trim();
+        if key == "service" {
+            return Err(format!("nested service specifications not supported at line {}", i + 1));
+        }
+
+        params.push((key.to_owned(), value.trim().to_owned()));
+    }
+
+    Ok(params)
+}
+
+#[cfg(test)]
+mod test {
+    use super::find;
+
+    #[test]
+    fn service_file() {
+        let content = "\
+            # comment\n\
+            [other]\n\
+            host=other.example.com\n\
+            \n\
+            [app]\n\
+            host = db.example.com\n\
+            port=5433\n\
+            options=-c search_path=app\n\
+            [next]\n\
+            user=next\n\
+        ";
+
+        let params = find(content, "app").unwrap().unwrap();
+        assert_eq!(params, [
+            ("host".to_owned(), "db.example.com".to_owned()),
+            ("port".to_owned(), "5433".to_owned()),
+            ("options".to_owned(), "-c search_path=app".to_owned()),
+        ]);
+
+        assert_eq!(find(content, "missing").unwrap(), None);
+        assert!(find("[app]\nhost\n", "app").is_err());
+        assert!(find("[app]\nservice=other\n", "app").is_err());
+    }
+}
@@ -2,9 +2,10 @@
 use std::{backtrace::Backtrace, fmt, io, str::Utf8Error};
 
 use crate::{
-    connection::ParseError,
-    fetch::EmptyQueryError,
-    phase::UnsupportedAuth,
+    connection::{CredentialError, ParseError, SessionAttrsMismatch},
+    fetch::{EmptyQueryError, TimeoutError},
+    net::tls::TlsError,
+    phase::{AuthError, UnsupportedAuth},
     postgres::{ErrorResponse, ProtocolError},
     row::{DecodeError, RowNotFound},
 };
@@ -34,12 +35,17 @@ pub enum ErrorKind {
     Config(ParseError),
     Protocol(ProtocolError),
     Io(io::Error),
+    Tls(TlsError),
     Database(ErrorResponse),
     Utf8(std::str::Utf8Error),
     RowNotFound(RowNotFound),
     EmptyQuery(EmptyQueryError),
     UnsupportedAuth(UnsupportedAuth),
+    Auth(AuthError),
+    Credential(CredentialError),
     Decode(DecodeError),
+    SessionAttrs(SessionAttrsMismatch),
+    Timeout(TimeoutError),
 }
 
 macro_rules! from {
@@ -57,13 +63,18 @@ from!(<ErrorKind>e => e);
 from!(<ParseError>e => ErrorKind::Config(e));
 from!(<ProtocolError>e => ErrorKind::Protocol(e));
 from!(<std::io::Error>e => ErrorKind::Io(e));
+from!(<TlsError>e => ErrorKind::Tls(e));
 from!(<ErrorResponse>e => ErrorKind::Database(e));
 from!(<Utf8Error>e => ErrorKind::Utf8(e));
 from!(<RowNotFound>e => ErrorKind::RowNotFound(e));
 from!(<EmptyQueryError>e => ErrorKind::EmptyQuery(e));
 from!(<UnsupportedAuth>e => ErrorKind::UnsupportedAuth(e));
+from!(<AuthError>e => ErrorKind::Auth(e));
+from!(<CredentialError>e => ErrorKind::Credential(e));
 
 from!(<DecodeError>e => ErrorKind::Decode(e));
+from!(<SessionAttrsMismatch>e => ErrorKind::SessionAttrs(e));
+from!(<TimeoutError>e => ErrorKind::Timeout(e));
 
 impl std::error::Error for Error { }
 
@@ -101,12 +112,17 @@ impl fmt::Display for ErrorKind {
             Self::Config(e) => e.fmt(f),
             Self::Protocol(e) => e.fmt(f),
             Self::Io(e) => e.fmt(f),
+            Self::Tls(e) => e.fmt(f),
             Self::Database(e) => e.fmt(f),
             Self::UnsupportedAuth(e) => e.fmt(f),
+            Self::Auth(e) => e.fmt(f),
+            Self::Credential(e) => e.fmt(f),
             Self::RowNotFound(e) => e.fmt(f),
             Self::EmptyQuery(e) => e.fmt(f),
             Self::Decode(e) => e.fmt(f),
-            Self::Utf8(e) => e.fmt(f)
+            Self::Utf8(e) => e.fmt(f),
+            Self::SessionAttrs(e) => e.fmt(f),
+            Self::Timeout(e) => e.fmt(f),
         }
     }
 }
@@ -1,6 +1,6 @@
 use futures_core::Stream;
 use std::{
-    hash::{DefaultHasher, Hash, Hasher},
+    fmt,
     marker::PhantomData,
     mem,
     pin::Pin,
@@ -9,6 +9,7 @@ use std::{
         Poll::{self, *},
         ready,
     },
+    time::Duration,
 };
 
 use crate::{
@@ -16,7 +17,8 @@ use crate::{
     common::unit_error,
     encode::Encoded,
     ext::UsizeExt,
-    postgres::{PgFormat, backend, frontend},
+    net::Deadline,
+    postgres::{NoticeResponse, PgFormat, backend, frontend},
     sql::Sql,
     statement::{PortalName, StatementName},
     transport::PgTransport,
@@ -24,7 +26,6 @@ use crate::{
 
 #[derive(Debug)]
 pub struct PrepareData {
-    pub sqlid: u64,
     pub stmt: StatementName,
     pub cache_hit: bool,
     /// this field intended to be edited by called for `portal` params.
@@ -47,20 +48,12 @@ fn prepare(
     let persist = sql.persistent();
     let sql = sql.sql().trim();
 
-    let sqlid = {
-        let mut buf = DefaultHasher::new();
-        sql.hash(&mut buf);
-        buf.finish()
-    };
-
-    if persist {
-        if let Some(stmt) = io.get_stmt(sqlid) {
-            return PrepareData { sqlid, stmt, cache_hit: true, max_row: 0 };
-        }
+    if persist && let Some(stmt) = io.get_stmt(sql) {
+        return PrepareData { stmt, cache_hit: true, max_row: 0 };
     }
 
     let stmt = match persist {
-        true => StatementName::next(),
+        true => io.next_stmt().unwrap_or_else(StatementName::unnamed),
         false => StatementName::unnamed(),
     };
 
@@ -72,7 +65,7 @@ fn prepare(
     });
     io.send(frontend::Flush);
 
-    PrepareData { sqlid, stmt, cache_hit: false, max_row: 0 }
+    PrepareData { stmt, cache_hit: false, max_row: 0 }
 }
 
 /// Write Prepare statement to `io`.
@@ -154,6 +147,9 @@ pub struct FetchStream<'val, SQL, ExeFut, IO, M> {
     params: Vec<Encoded<'val>>,
     max_row: u32,
     cmd: Option<backend::CommandComplete>,
+    timeout: Option<Duration>,
+    deadline: Option<Deadline>,
+    capture: bool,
     _p: PhantomData<M>,
 }
 
@@ -168,6 +164,17 @@ enum Phase<ExeFut> {
     RowDescription,
     DataRow(Row),
     ReadyForQuery,
+    Cancel { f: Option<Cancel> },
+    Drain,
+}
+
+/// Cancel request of timed out query.
+struct Cancel(Pin<Box<dyn Future<Output = Result<()>> + Send>>);
+
+impl fmt::Debug for Cancel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancel")
+    }
 }
 
 impl<'val, SQL, ExeFut, IO, M> FetchStream<'val, SQL, ExeFut, IO, M> {
@@ -176,6 +183,7 @@ impl<'val, SQL, ExeFut, IO, M> FetchStream<'val, SQL, ExeFut, IO, M> {
         exe: ExeFut,
         params: Vec<Encoded<'val>>,
         max_row: u32,
+        timeout: Option<Duration>,
     ) -> Self {
         Self {
             sql,
@@ -185,11 +193,41 @@ impl<'val, SQL, ExeFut, IO, M> FetchStream<'val, SQL, ExeFut, IO, M> {
             params,
             max_row,
             cmd: None,
+            timeout,
+            deadline: None,
+            capture: false,
             _p: PhantomData,
         }
     }
 }
 
+impl<SQL, ExeFut, IO, M> FetchStream<'_, SQL, ExeFut, IO, M>
+where
+    IO: PgTransport,
+{
+    /// Deadline elapsed, cancel the query and discard all messages until `ReadyForQuery`.
+    fn timed_out(&mut self) {
+        let io = self.io.as_mut().unwrap();
+
+        // `Sync` is not yet sent while preparing statement
+        if let Phase::PrepareComplete = self.phase {
+            // the statement may still be created, and it is not added to the cache
+            let stmt = &self.data.as_ref().unwrap().stmt;
+            if !stmt.is_unnamed() {
+                io.send(frontend::Close {
+                    variant: b'S',
+                    name: stmt.as_str(),
+                });
+            }
+            io.send(frontend::Sync);
+        }
+        io.ready_request();
+
+        let f = io.cancel_token().map(|token|Cancel(Box::pin(async move { token.cancel().await })));
+        self.phase = Phase::Cancel { f };
+    }
+}
+
 impl<SQL, ExeFut, IO, M> Stream for FetchStream<'_, SQL, ExeFut, IO, M>
 where
     SQL: Sql + Unpin,
@@ -202,10 +240,40 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let me = self.get_mut();
 
+        let poll = me.poll_fetch(cx);
+
+        // deadline is only checked when waiting for the backend
+        if poll.is_pending()
+            && let Some(deadline) = me.deadline.as_mut()
+            && deadline.poll_elapsed(cx).is_ready()
+        {
+            me.deadline = None;
+            me.timed_out();
+            return me.poll_fetch(cx);
+        }
+
+        poll
+    }
+}
+
+impl<SQL, ExeFut, IO, M> FetchStream<'_, SQL, ExeFut, IO, M>
+where
+    SQL: Sql + Unpin,
+    ExeFut: Future<Output = Result<IO>> + Unpin,
+    IO: PgTransport + Unpin,
+    M: StreamMap + Unpin,
+{
+    fn poll_fetch(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<M::Output>>> {
+        let me = self;
+
         loop {
             match &mut me.phase {
                 Phase::Connect { f } => {
-                    let io = ready!(Pin::new(f).poll(cx)?);
+                    let mut io = ready!(Pin::new(f).poll(cx)?);
+                    if me.capture {
+                        io.capture_notices();
+                    }
+                    me.deadline = me.timeout.or_else(||io.query_timeout()).map(Deadline::new);
                     me.io = Some(io);
                     me.phase = Phase::Prepare;
                 },
@@ -220,7 +288,9 @@ where
                     let io = me.io.as_mut().unwrap();
                     let data = me.data.as_ref().unwrap();
                     ready!(io.poll_recv::<backend::ParseComplete>(cx)?);
-                    io.add_stmt(data.sqlid, data.stmt.clone());
+                    if !data.stmt.is_unnamed() {
+                        io.add_stmt(me.sql.sql().trim(), data.stmt.clone());
+                    }
                     me.phase = Phase::Portal;
                 },
                 Phase::Portal => {
@@ -288,6 +358,20 @@ where
                     ready!(me.io.as_mut().unwrap().poll_recv::<backend::ReadyForQuery>(cx)?);
                     me.phase = Phase::Complete;
                 },
+                Phase::Cancel { f } => {
+                    if let Some(Cancel(f)) = f
+                        && let Err(_err) = ready!(f.as_mut().poll(cx))
+                    {
+                        #[cfg(feature = "log")]
+                        log::warn!("failed to cancel timed out query: {_err}");
+                    }
+                    me.phase = Phase::Drain;
+                },
+                Phase::Drain => {
+                    ready!(me.io.as_mut().unwrap().poll_ready(cx)?);
+                    me.phase = Phase::Complete;
+                    return Ready(Some(Err(TimeoutError.into())));
+                },
                 Phase::Complete => return Ready(None),
             }
         }
@@ -308,14 +392,47 @@ impl<'val, SQL, ExeFut, IO, M, C> Fetch<'val, SQL, ExeFut, IO, M, C> {
         params: Vec<Encoded<'val>>,
         collect: C,
         max_row: u32,
+        timeout: Option<Duration>,
     ) -> Self {
         Self {
-            fetch: FetchStream::new(sql, exe, params, max_row),
+            fetch: FetchStream::new(sql, exe, params, max_row, timeout),
             collect,
         }
     }
 }
 
+impl<'val, SQL, ExeFut, IO, M, C> Fetch<'val, SQL, ExeFut, IO, M, C>
+where
+    IO: PgTransport,
+{
+    /// Collect notices raised by the query, e.g. from `RAISE NOTICE`, and return them with the result.
+    ///
+    /// Collected notices are not passed to the [`NoticeHandler`][1], unless the query failed,
+    /// or the future is dropped before completion.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn app(mut conn: postro::Connection) -> postro::Result<()> {
+    /// let (_, notices) = postro::query("SELECT migrate()", &mut conn)
+    ///     .execute()
+    ///     .with_notices()
+    ///     .await?;
+    ///
+    /// for notice in notices {
+    ///     println!("{}", notice.message());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [1]: crate::connection::NoticeHandler
+    pub fn with_notices(mut self) -> WithNotices<'val, SQL, ExeFut, IO, M, C> {
+        self.fetch.capture = true;
+        WithNotices { fetch: self }
+    }
+}
+
 impl<SQL, ExeFut, IO, M, C> Future for Fetch<'_, SQL, ExeFut, IO, M, C>
 where
     SQL: Sql + Unpin,
@@ -337,6 +454,54 @@ where
     }
 }
 
+/// Future returned from [`Fetch::with_notices`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WithNotices<'val, SQL, ExeFut, IO: PgTransport, M, C> {
+    fetch: Fetch<'val, SQL, ExeFut, IO, M, C>,
+}
+
+impl<SQL, ExeFut, IO: PgTransport, M, C> WithNotices<'_, SQL, ExeFut, IO, M, C> {
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        self.fetch.fetch.io.as_mut().map(PgTransport::take_notices).unwrap_or_default()
+    }
+
+    fn release_notices(&mut self) {
+        if let Some(io) = self.fetch.fetch.io.as_mut() {
+            io.release_notices();
+        }
+    }
+}
+
+impl<SQL, ExeFut, IO, M, C> Future for WithNotices<'_, SQL, ExeFut, IO, M, C>
+where
+    SQL: Sql + Unpin,
+    ExeFut: Future<Output = Result<IO>> + Unpin,
+    IO: PgTransport + Unpin,
+    M: StreamMap + Unpin,
+    C: FetchCollect<M::Output> + Unpin,
+{
+    type Output = Result<(C::Output, Vec<NoticeResponse>)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let me = self.get_mut();
+        match ready!(Pin::new(&mut me.fetch).poll(cx)) {
+            Ok(output) => Ready(Ok((output, me.take_notices()))),
+            Err(err) => {
+                me.release_notices();
+                Ready(Err(err))
+            },
+        }
+    }
+}
+
+impl<SQL, ExeFut, IO: PgTransport, M, C> Drop for WithNotices<'_, SQL, ExeFut, IO, M, C> {
+    fn drop(&mut self) {
+        // stop collecting if not completed
+        self.release_notices();
+    }
+}
+
 /// Adapter to process a [`Row`].
 pub trait StreamMap {
     /// Processed row.
@@ -363,3 +528,166 @@ unit_error! {
     pub struct EmptyQueryError("empty query string");
 }
 
+unit_error! {
+    /// An error when query is not completed within the time limit.
+    pub struct TimeoutError("query timed out");
+}
+
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use std::{sync::{Arc, Mutex}, time::Duration};
+
+    use crate::{
+        Connection, Result,
+        connection::test::{fake_config, fake_server},
+        error::ErrorKind,
+        transport::test::ScriptedTransport,
+    };
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    fn syncs(io: &ScriptedTransport) -> usize {
+        io.sent().iter().filter(|(msgtype,_)|*msgtype == b'S').count()
+    }
+
+    #[test]
+    fn timeout_prepare_complete() {
+        let mut io = ScriptedTransport::new();
+        io.push_pending();
+        // late `ParseComplete` and the cancelled error
+        io.push(b'1', &b""[..]);
+        io.push(b'E', &b"SERROR\0C57014\0Mcanceling statement due to user request\0\0"[..]);
+        io.push(b'Z', &b"I"[..]);
+        io.push_query("ok");
+
+        runtime().block_on(async {
+            let result: Result<String> = crate::query_scalar("SELECT pg_sleep(1)", &mut io)
+                .timeout(Duration::from_millis(10))
+                .fetch_one()
+                .await;
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Timeout(_)));
+
+            // `Sync` is sent on timeout, because only `Parse` and `Flush` is sent
+            assert_eq!(syncs(&io), 1);
+
+            let value: String = crate::query_scalar("SELECT 'ok'", &mut io).fetch_one().await.unwrap();
+            assert_eq!(value, "ok");
+        });
+    }
+
+    #[test]
+    fn timeout_data_row() {
+        let mut io = ScriptedTransport::new();
+        io.push(b'1', &b""[..]);
+        io.push(b'2', &b""[..]);
+        io.push_text_column();
+        io.push_text_row("a");
+        io.push_pending();
+        io.push_text_row("b");
+        io.push(b'C', &b"SELECT 2\0"[..]);
+        io.push(b'Z', &b"I"[..]);
+        io.push_query("ok");
+
+        runtime().block_on(async {
+            let result: Result<Vec<String>> = crate::query_scalar("SELECT slow()", &mut io)
+                .timeout(Duration::from_millis(10))
+                .fetch_all()
+                .await;
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Timeout(_)));
+
+            // `Sync` is already sent with `Execute`
+            assert_eq!(syncs(&io), 1);
+
+            let value: String = crate::query_scalar("SELECT 'ok'", &mut io).fetch_one().await.unwrap();
+            assert_eq!(value, "ok");
+            assert_eq!(syncs(&io), 2);
+        });
+    }
+
+    #[test]
+    fn timeout_cancel() {
+        let (port, server) = fake_server(1);
+
+        runtime().block_on(async {
+            let mut conn = Connection::connect_with(fake_config(port)).await.unwrap();
+
+            let result: Result<String> = crate::query_scalar("SLEEP", &mut conn)
+                .timeout(Duration::from_millis(50))
+                .fetch_one()
+                .await;
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Timeout(_)));
+
+            let value: String = crate::query_scalar("SELECT 'ok'", &mut conn).fetch_one().await.unwrap();
+            assert_eq!(value, "SELECT 'ok'");
+        });
+
+        let received = server.join().unwrap();
+        assert_eq!(received.iter().filter(|(msgtype,_)|*msgtype == 0).count(), 1);
+    }
+
+    #[test]
+    fn timeout_prepare_close() {
+        let (port, server) = fake_server(1);
+
+        runtime().block_on(async {
+            let mut conn = Connection::connect_with(fake_config(port)).await.unwrap();
+
+            let result: Result<String> = crate::query_scalar("SLOW", &mut conn)
+                .timeout(Duration::from_millis(50))
+                .fetch_one()
+                .await;
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Timeout(_)));
+            assert_eq!(conn.statement_cache_len(), 0);
+
+            let value: String = crate::query_scalar("SELECT 'ok'", &mut conn).fetch_one().await.unwrap();
+            assert_eq!(value, "SELECT 'ok'");
+        });
+
+        // the statement created after the deadline is closed before the `Sync`
+        let received = server.join().unwrap();
+        let msgtypes = received.iter().map(|(msgtype,_)|*msgtype).collect::<Vec<_>>();
+        assert_eq!(msgtypes[..5], [0, b'P', b'H', b'C', b'S']);
+        assert_eq!(received[3].1, b"Sq1\0");
+    }
+
+    #[test]
+    fn with_notices() {
+        let (port, server) = fake_server(1);
+        let handled = Arc::new(Mutex::new(vec![]));
+        let handler = {
+            let handled = handled.clone();
+            move |notice: &crate::postgres::NoticeResponse| {
+                handled.lock().unwrap().push(notice.message().to_owned());
+            }
+        };
+        let config = fake_config(port).with_notice_handler(handler);
+
+        runtime().block_on(async {
+            let mut conn = Connection::connect_with(config).await.unwrap();
+
+            let (_, notices) = crate::query_scalar::<_, _, String>("SELECT NOTICE", &mut conn)
+                .fetch_one()
+                .with_notices()
+                .await
+                .unwrap();
+            assert_eq!(notices.len(), 1);
+            assert_eq!(notices[0].message(), "SELECT NOTICE");
+            assert!(handled.lock().unwrap().is_empty());
+
+            // notices of failed query are passed to the handler
+            let result = crate::query_scalar::<_, _, String>("ERROR NOTICE", &mut conn)
+                .fetch_one()
+                .with_notices()
+                .await;
+            assert!(result.is_err());
+            assert_eq!(*handled.lock().unwrap(), ["ERROR NOTICE"]);
+
+            let _: String = crate::query_scalar("SELECT NOTICE", &mut conn).fetch_one().await.unwrap();
+            assert_eq!(*handled.lock().unwrap(), ["ERROR NOTICE", "SELECT NOTICE"]);
+        });
+        server.join().unwrap();
+    }
+}
@@ -10,7 +10,7 @@
 //! # async fn app() -> postro::Result<()> {
 //! let mut conn = Connection::connect_env().await?;
 //!
-//! let res = postro::query::<_, _, (i32,String)>("SELECT 420,$1", &mut conn)
+//! let res = postro::query_as::<_, _, (i32,String)>("SELECT 420,$1", &mut conn)
 //!     .bind("Foo")
 //!     .fetch_one()
 //!     .await?;
@@ -34,7 +34,7 @@
 //! for i in 0..14 {
 //!     let mut pool = pool.clone();
 //!     let t = tokio::spawn(async move {
-//!         postro::execute("INSERT INTO foo(id) VALUES($1)", &mut pool)
+//!         postro::query("INSERT INTO foo(id) VALUES($1)", &mut pool)
 //!             .bind(i)
 //!             .execute()
 //!             .await
@@ -46,7 +46,7 @@
 //!     h.await.unwrap();
 //! }
 //!
-//! let foos = postro::query::<_, _, (i32,)>("SELECT * FROM foo", &mut pool)
+//! let foos = postro::query_as::<_, _, (i32,)>("SELECT * FROM foo", &mut pool)
 //!     .fetch_all()
 //!     .await?;
 //!
@@ -66,7 +66,7 @@
 //!
 //! let mut tx = postro::begin(&mut conn).await?;
 //!
-//! let _res = postro::query::<_, _, (i32,String)>("INSERT INTO foo(id) VALUES($1)", &mut tx)
+//! let _res = postro::query("INSERT INTO foo(id) VALUES($1)", &mut tx)
 //!     .bind(14)
 //!     .execute()
 //!     .await?;
@@ -109,6 +109,7 @@ mod fetch;
 // Connection
 pub mod connection;
 pub mod pool;
+pub mod listener;
 
 // Integration
 pub mod types;
@@ -131,6 +132,8 @@ pub use connection::{Connection, Config};
 #[doc(inline)]
 pub use pool::{Pool, PoolConfig};
 #[doc(inline)]
+pub use listener::PgListener;
+#[doc(inline)]
 pub use query::{query, query_as, query_scalar};
 #[doc(inline)]
 pub use phase::{startup, begin};
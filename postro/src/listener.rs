@@ -0,0 +1,253 @@
+//! The [`PgListener`] type.
+use std::{collections::BTreeSet, time::Duration};
+
+use crate::{
+    Config, Connection, Pool, Result,
+    connection::Notification,
+    error::ErrorKind,
+    net,
+};
+
+/// The minimum reconnect backoff, prevent reconnecting in a busy loop.
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+
+/// A dedicated connection which listen for notifications and reconnect when the connection is lost.
+///
+/// The connection is not part of any [`Pool`], when created with [`PgListener::from_pool`],
+/// it only uses the pool connection config.
+///
+/// When the connection is lost, [`PgListener::recv`] reconnects with exponential backoff, issues
+/// `LISTEN` for all listened channels, then returns [`ListenerEvent::Reconnected`]. Notifications
+/// sent while disconnected are lost, consumers should resync their state after receiving it.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn app() -> postro::Result<()> {
+/// use postro::listener::{ListenerEvent, PgListener};
+///
+/// let mut listener = PgListener::connect_env().await?;
+/// listener.listen("cache_invalidation").await?;
+///
+/// loop {
+///     match listener.recv().await {
+///         ListenerEvent::Notification(notification) => println!("invalidate {}", notification.payload()),
+///         ListenerEvent::Reconnected => println!("invalidate all"),
+///     }
+/// }
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// All constructor will panic if `tokio` feature is not enabled.
+#[derive(Debug)]
+pub struct PgListener {
+    config: Config,
+    conn: Connection,
+    channels: BTreeSet<String>,
+    lost: bool,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// Event received by [`PgListener`].
+#[derive(Debug)]
+pub enum ListenerEvent {
+    /// A notification from listened channels.
+    Notification(Notification),
+    /// The connection was lost and is re-established, notifications in between may be missed.
+    Reconnected,
+}
+
+impl PgListener {
+    /// Connect listener via environment variables.
+    ///
+    /// See [`Config::from_env`] for more details.
+    pub async fn connect_env() -> Result<Self> {
+        Self::connect_with(Config::from_env()).await
+    }
+
+    /// Connect listener via url.
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with(Config::parse(url)?).await
+    }
+
+    /// Connect listener with the connection config of given [`Pool`].
+    pub async fn from_pool(pool: &Pool) -> Result<Self> {
+        Self::connect_with(pool.config().clone()).await
+    }
+
+    /// Connect listener with provided config.
+    pub async fn connect_with(config: Config) -> Result<Self> {
+        let conn = Connection::connect_with(config.clone()).await?;
+        Ok(Self {
+            config,
+            conn,
+            channels: BTreeSet::new(),
+            lost: false,
+            min_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        })
+    }
+
+    /// Set the reconnect backoff, the delay is doubled after each failed attempt, up to `max`.
+    ///
+    /// The default is 500 milliseconds, up to 30 seconds. The minimum is 10 milliseconds.
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min.max(MIN_BACKOFF);
+        self.max_backoff = max.max(self.min_backoff);
+        self
+    }
+
+    /// Start listening for notification on `channel`.
+    ///
+    /// If the connection is lost, the next [`PgListener::recv`] reconnects.
+    pub async fn listen(&mut self, channel: &str) -> Result<()> {
+        let result = self.conn.listen(channel).await;
+        self.check(result)?;
+        self.channels.insert(channel.to_owned());
+        Ok(())
+    }
+
+    /// Stop listening for notification on `channel`.
+    pub async fn unlisten(&mut self, channel: &str) -> Result<()> {
+        self.channels.remove(channel);
+        let result = self.conn.unlisten(channel).await;
+        self.check(result)
+    }
+
+    /// Stop listening for notification on all channels.
+    pub async fn unlisten_all(&mut self) -> Result<()> {
+        self.channels.clear();
+        let result = self.conn.unlisten_all().await;
+        self.check(result)
+    }
+
+    /// Mark the connection as lost on I/O error.
+    fn check(&mut self, result: Result<()>) -> Result<()> {
+        if let Err(err) = &result
+            && let ErrorKind::Io(_) = err.kind()
+        {
+            self.lost = true;
+        }
+        result
+    }
+
+    /// Returns listened channels.
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(String::as_str)
+    }
+
+    /// Returns the underlying [`Connection`].
+    ///
+    /// The connection can be used for query, notifications received in the meantime are buffered.
+    pub fn connection(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+
+    /// Receive the next [`ListenerEvent`].
+    ///
+    /// If the connection is lost, this retry to reconnect indefinitely.
+    ///
+    /// This method is cancel safe, if cancelled while reconnecting, the next call will
+    /// continue to reconnect.
+    pub async fn recv(&mut self) -> ListenerEvent {
+        loop {
+            if self.lost {
+                self.reconnect().await;
+                self.lost = false;
+                return ListenerEvent::Reconnected;
+            }
+
+            match self.conn.recv_notification().await {
+                Ok(notification) => return ListenerEvent::Notification(notification),
+                Err(_err) => {
+                    #[cfg(feature = "log")]
+                    log::warn!("listener connection lost: {_err}");
+                    self.lost = true;
+                },
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) {
+        let mut backoff = self.min_backoff;
+
+        loop {
+            match self.try_reconnect().await {
+                Ok(conn) => {
+                    self.conn = conn;
+                    return;
+                },
+                Err(_err) => {
+                    #[cfg(feature = "log")]
+                    log::warn!("listener failed to reconnect, retry in {backoff:?}: {_err}");
+                },
+            }
+
+            net::sleep(backoff).await;
+            backoff = next_backoff(backoff, self.max_backoff);
+        }
+    }
+
+    async fn try_reconnect(&self) -> Result<Connection> {
+        let mut conn = Connection::connect_with(self.config.clone()).await?;
+        for channel in &self.channels {
+            conn.listen(channel).await?;
+        }
+        Ok(conn)
+    }
+}
+
+/// Returns the doubled `backoff`, up to `max`.
+fn next_backoff(backoff: Duration, max: Duration) -> Duration {
+    backoff.saturating_mul(2).min(max)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use super::*;
+    use crate::connection::test::{fake_config, fake_server};
+
+    #[test]
+    fn backoff() {
+        let ms = Duration::from_millis;
+        assert_eq!(next_backoff(ms(500), ms(30_000)), ms(1000));
+        assert_eq!(next_backoff(ms(20_000), ms(30_000)), ms(30_000));
+        assert_eq!(next_backoff(Duration::MAX, Duration::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn reconnect() {
+        let (port, server) = fake_server(2);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut listener = PgListener::connect_with(fake_config(port))
+                .await
+                .unwrap()
+                .backoff(Duration::ZERO, Duration::ZERO);
+            assert_eq!(listener.min_backoff, MIN_BACKOFF);
+            assert_eq!(listener.max_backoff, MIN_BACKOFF);
+
+            listener.listen("a").await.unwrap();
+            listener.listen("b").await.unwrap();
+
+            let _: String = crate::query_scalar("SHUTDOWN", listener.connection()).fetch_one().await.unwrap();
+            assert!(listener.listen("c").await.is_err());
+            assert!(listener.lost);
+
+            assert!(matches!(listener.recv().await, ListenerEvent::Reconnected));
+            assert_eq!(listener.channels().collect::<Vec<_>>(), ["a", "b"]);
+        });
+
+        let listens = server
+            .join()
+            .unwrap()
+            .into_iter()
+            .filter(|(msgtype,_)|*msgtype == b'Q')
+            .map(|(_,body)|String::from_utf8(body).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(listens, ["LISTEN \"a\"\0", "LISTEN \"b\"\0", "LISTEN \"a\"\0", "LISTEN \"b\"\0"]);
+    }
+}
@@ -1,2 +1,82 @@
+use std::{
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+
 mod socket;
+pub mod tls;
 pub use socket::Socket;
+
+/// Await `future` with optional time limit.
+///
+/// Returns [`io::ErrorKind::TimedOut`] error if the time limit elapsed.
+pub async fn timeout<F: Future>(duration: Option<Duration>, future: F) -> io::Result<F::Output> {
+    let Some(duration) = duration else {
+        return Ok(future.await);
+    };
+
+    #[cfg(feature = "tokio")]
+    {
+        tokio::time::timeout(duration, future)
+            .await
+            .map_err(|_|io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    {
+        let _ = duration;
+        panic!("runtime disabled")
+    }
+}
+
+/// Wait until `duration` has elapsed.
+///
+/// # Panics
+///
+/// Panics if `tokio` feature is not enabled.
+pub async fn sleep(duration: Duration) {
+    let mut deadline = Deadline::new(duration);
+    std::future::poll_fn(|cx|deadline.poll_elapsed(cx)).await
+}
+
+/// A timer which elapsed after given duration.
+#[derive(Debug)]
+pub struct Deadline {
+    #[cfg(feature = "tokio")]
+    sleep: std::pin::Pin<Box<tokio::time::Sleep>>,
+}
+
+impl Deadline {
+    /// Start the timer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tokio` feature is not enabled.
+    pub fn new(duration: Duration) -> Self {
+        #[cfg(feature = "tokio")]
+        {
+            Self { sleep: Box::pin(tokio::time::sleep(duration)) }
+        }
+
+        #[cfg(not(feature = "tokio"))]
+        {
+            let _ = duration;
+            panic!("runtime disabled")
+        }
+    }
+
+    /// Poll whether the deadline is elapsed.
+    pub fn poll_elapsed(&mut self, cx: &mut Context) -> Poll<()> {
+        #[cfg(feature = "tokio")]
+        {
+            self.sleep.as_mut().poll(cx)
+        }
+
+        #[cfg(not(feature = "tokio"))]
+        {
+            let _ = cx;
+            unreachable!()
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::io;
 
-/// An either `TcpStream` or `Socket`, which implement
+use crate::connection::Config;
+
+/// An either `TcpStream`, `Socket` or TLS stream, which implement
 /// `AsyncRead` and `AsyncWrite` transparently.
 ///
 /// Require `tokio` feature, otherwise panic at runtime.
@@ -8,19 +10,24 @@ pub struct Socket {
     kind: Kind,
 }
 
+#[allow(clippy::enum_variant_names, reason = "runtime prefixed, more runtime may be added")]
 enum Kind {
     #[cfg(feature = "tokio")]
     TokioTcp(tokio::net::TcpStream),
     #[cfg(all(feature = "tokio", unix))]
     TokioUnixSocket(tokio::net::UnixStream),
+    #[cfg(feature = "tls")]
+    TokioTls(Box<tokio_rustls::client::TlsStream<tokio::net::TcpStream>>),
 }
 
 impl Socket {
-    pub async fn connect_tcp(host: &str, port: u16) -> io::Result<Socket> {
+    /// Connect via TCP, with keepalive options from given config.
+    pub async fn connect_tcp(host: &str, port: u16, config: &Config) -> io::Result<Socket> {
         #[cfg(feature = "tokio")]
         {
             let socket = tokio::net::TcpStream::connect((host,port)).await?;
             socket.set_nodelay(true)?;
+            set_keepalive(socket2::SockRef::from(&socket), config)?;
             #[cfg(feature = "log")]
             log::debug!("Connected via TCP Stream: {:?}", socket.local_addr());
             Ok(Socket { kind: Kind::TokioTcp(socket) })
@@ -28,7 +35,7 @@ impl Socket {
 
         #[cfg(not(feature = "tokio"))]
         {
-            let _ = (host,port);
+            let _ = (host,port,config);
             panic!("runtime disabled")
         }
     }
@@ -42,13 +49,76 @@ impl Socket {
             Ok(Socket { kind: Kind::TokioUnixSocket(socket) })
         }
 
-        #[cfg(not(all(feature = "tokio", unix)))]
+        #[cfg(all(feature = "tokio", not(unix)))]
+        {
+            let _ = path;
+            Err(io::Error::new(io::ErrorKind::Unsupported, "Unix domain socket is not supported"))
+        }
+
+        #[cfg(not(feature = "tokio"))]
         {
             let _ = path;
             panic!("runtime disabled")
         }
     }
 
+    /// Returns `true` if socket is a plain TCP stream.
+    pub fn is_tcp(&self) -> bool {
+        #[cfg(feature = "tokio")]
+        if let Kind::TokioTcp(_) = self.kind {
+            return true;
+        }
+        false
+    }
+
+    /// Returns `true` if socket is a TLS stream.
+    pub fn is_tls(&self) -> bool {
+        #[cfg(feature = "tls")]
+        if let Kind::TokioTls(_) = self.kind {
+            return true;
+        }
+        false
+    }
+
+    /// Perform TLS handshake over plain TCP stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if socket is not a plain TCP stream.
+    #[cfg(feature = "tls")]
+    pub async fn upgrade_tls(
+        self,
+        connector: tokio_rustls::TlsConnector,
+        server_name: rustls::pki_types::ServerName<'static>,
+    ) -> io::Result<Socket> {
+        let Kind::TokioTcp(socket) = self.kind else {
+            panic!("TLS upgrade on non TCP stream")
+        };
+        let socket = connector.connect(server_name, socket).await?;
+        #[cfg(feature = "log")]
+        log::debug!("TLS handshake completed: {:?}", socket.get_ref().1.protocol_version());
+        Ok(Socket { kind: Kind::TokioTls(Box::new(socket)) })
+    }
+
+    /// Returns `tls-server-end-point` channel binding data if socket is a TLS stream.
+    pub fn tls_server_end_point(&self) -> Option<Result<Vec<u8>, super::tls::TlsError>> {
+        #[cfg(feature = "tls")]
+        if let Kind::TokioTls(tls) = &self.kind {
+            let cert = tls.get_ref().1.peer_certificates()?.first()?;
+            return Some(super::tls::tls_server_end_point(cert));
+        }
+        None
+    }
+
+    /// Returns the negotiated ALPN protocol if socket is a TLS stream.
+    #[cfg(feature = "tls")]
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match &self.kind {
+            Kind::TokioTls(tls) => tls.get_ref().1.alpn_protocol(),
+            _ => None,
+        }
+    }
+
     pub fn poll_shutdown(&mut self, _cx: &mut std::task::Context) -> std::task::Poll<io::Result<()>> {
         #[cfg(all(feature = "tokio", unix))]
         {
@@ -66,6 +136,42 @@ impl Socket {
     }
 }
 
+/// Apply TCP keepalive and user timeout options.
+///
+/// Options that are not supported by the platform are ignored.
+#[cfg(feature = "tokio")]
+fn set_keepalive(socket: socket2::SockRef, config: &Config) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(timeout) = config.tcp_user_timeout {
+        socket.set_tcp_user_timeout(Some(timeout))?;
+    }
+
+    if !config.keepalives {
+        return Ok(());
+    }
+
+    let mut keepalive = socket2::TcpKeepalive::new();
+    if let Some(idle) = config.keepalives_idle {
+        keepalive = keepalive.with_time(idle);
+    }
+    #[cfg(any(
+        target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios",
+        target_os = "freebsd", target_os = "netbsd", target_os = "windows",
+    ))]
+    if let Some(interval) = config.keepalives_interval {
+        keepalive = keepalive.with_interval(interval);
+    }
+    #[cfg(any(
+        target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios",
+        target_os = "freebsd", target_os = "netbsd",
+    ))]
+    if let Some(count) = config.keepalives_count {
+        keepalive = keepalive.with_retries(count);
+    }
+
+    socket.set_tcp_keepalive(&keepalive)
+}
+
 #[cfg(feature = "tokio")]
 impl tokio::io::AsyncRead for Socket {
     fn poll_read(
@@ -78,6 +184,8 @@ impl tokio::io::AsyncRead for Socket {
             Kind::TokioTcp(t) => Pin::new(t).poll_read(cx, buf),
             #[cfg(unix)]
             Kind::TokioUnixSocket(u) => Pin::new(u).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Kind::TokioTls(t) => Pin::new(t).poll_read(cx, buf),
         }
     }
 }
@@ -94,6 +202,8 @@ impl tokio::io::AsyncWrite for Socket {
             Kind::TokioTcp(t) => Pin::new(t).poll_write(cx, buf),
             #[cfg(unix)]
             Kind::TokioUnixSocket(u) => Pin::new(u).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Kind::TokioTls(t) => Pin::new(t).poll_write(cx, buf),
         }
     }
 
@@ -107,6 +217,8 @@ impl tokio::io::AsyncWrite for Socket {
             Kind::TokioTcp(t) => Pin::new(t).poll_write_vectored(cx, bufs),
             #[cfg(unix)]
             Kind::TokioUnixSocket(u) => Pin::new(u).poll_write_vectored(cx, bufs),
+            #[cfg(feature = "tls")]
+            Kind::TokioTls(t) => Pin::new(t).poll_write_vectored(cx, bufs),
         }
     }
 
@@ -132,6 +244,8 @@ impl tokio::io::AsyncWrite for Socket {
             Kind::TokioTcp(t) => Pin::new(t).poll_shutdown(cx),
             #[cfg(unix)]
             Kind::TokioUnixSocket(u) => Pin::new(u).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Kind::TokioTls(t) => Pin::new(t).poll_shutdown(cx),
         }
     }
 }
@@ -143,6 +257,8 @@ impl std::fmt::Debug for Socket {
             Kind::TokioTcp(tcp) => std::fmt::Debug::fmt(&tcp, _f),
             #[cfg(all(feature = "tokio", unix))]
             Kind::TokioUnixSocket(unix) => std::fmt::Debug::fmt(&unix, _f),
+            #[cfg(feature = "tls")]
+            Kind::TokioTls(tls) => std::fmt::Debug::fmt(&tls.get_ref().0, _f),
             #[cfg(not(feature = "tokio"))]
             _ => Ok(())
         }
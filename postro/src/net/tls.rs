@@ -0,0 +1,857 @@
+//! SSL negotiation.
+//!
+//! <https://www.postgresql.org/docs/current/protocol-flow.html#PROTOCOL-FLOW-SSL>
+use std::{fmt, io};
+
+use super::Socket;
+use crate::{Result, connection::{Config, SslMode, SslNegotiation}};
+
+/// ALPN protocol name of postgres.
+#[cfg(feature = "tls")]
+const ALPN_POSTGRESQL: &[u8] = b"postgresql";
+
+/// An error when establishing SSL connection.
+pub enum TlsError {
+    /// SSL is required, but `tls` feature is not enabled.
+    Disabled,
+    /// SSL is required, but server does not support SSL.
+    NotSupported,
+    /// Failed to setup SSL connection.
+    Config(String),
+    /// SSL handshake with the server failed.
+    Handshake(io::Error),
+    /// Direct SSL negotiation is used with sslmode weaker than [`SslMode::Require`].
+    WeakSslMode(SslMode),
+    /// Direct SSL connection is established without `postgresql` ALPN protocol.
+    NoAlpn,
+}
+
+/// Perform SSL negotiation to `host` as configured in [`Config::ssl_mode`][1].
+///
+/// Non TCP socket is returned as is.
+///
+/// [1]: crate::Config
+#[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+pub async fn negotiate(socket: Socket, config: &Config, host: &str) -> Result<Socket> {
+    // weaker sslmode could lead to unintended fallback to plaintext authentication
+    // when the server does not support direct SSL handshake
+    if config.ssl_negotiation == SslNegotiation::Direct
+        && matches!(config.ssl_mode, SslMode::Disable | SslMode::Prefer)
+    {
+        return Err(TlsError::WeakSslMode(config.ssl_mode).into());
+    }
+
+    if !socket.is_tcp() {
+        return Ok(socket);
+    }
+
+    match config.ssl_mode {
+        SslMode::Disable => Ok(socket),
+        #[cfg(not(feature = "tls"))]
+        SslMode::Prefer => Ok(socket),
+        #[cfg(not(feature = "tls"))]
+        _ => Err(TlsError::Disabled.into()),
+        #[cfg(feature = "tls")]
+        _ => rustls::negotiate(socket, config, host).await,
+    }
+}
+
+/// Compute `tls-server-end-point` channel binding data from server DER certificate.
+///
+/// The data is the hash of the certificate, using the hash function of the certificate signature
+/// algorithm, with MD5 and SHA-1 replaced by SHA-256. For RSASSA-PSS, the hash function is taken
+/// from the signature parameters.
+///
+/// Returns error if the signature algorithm is unsupported, e.g. EdDSA, which have no
+/// channel binding defined.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc5929#section-4.1>
+#[cfg(feature = "tls")]
+pub fn tls_server_end_point(cert: &[u8]) -> Result<Vec<u8>, TlsError> {
+    use sha2::{Digest, Sha256, Sha384, Sha512};
+
+    // 1.2.840.113549.1.1.x, RSA PKCS #1
+    const RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01];
+    // 1.2.840.10045.4.x, ECDSA
+    const ECDSA: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04];
+    // 2.16.840.1.101.3.4.2.x, NIST hash algorithms
+    const NIST_HASH: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02];
+    // 1.3.14.3.2.26
+    const SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+
+    let Some((oid, params)) = signature_algorithm(cert) else {
+        return Err(TlsError::Config("failed to parse server certificate signature algorithm".into()));
+    };
+
+    let hash = match oid.strip_prefix(RSA).or(oid.strip_prefix(ECDSA)) {
+        // RSASSA-PSS, the hash is in the parameters
+        Some([0x0a]) => match pss_hash_algorithm(params) {
+            Some(None) => Some(256),
+            Some(Some(SHA1)) => Some(256),
+            Some(Some(hash)) => match hash.strip_prefix(NIST_HASH) {
+                Some([0x01]) => Some(256),
+                Some([0x02]) => Some(384),
+                Some([0x03]) => Some(512),
+                _ => None,
+            },
+            None => None,
+        },
+        // md5WithRSA, sha1WithRSA, sha256WithRSA, ecdsa-with-SHA1, ecdsa-with-SHA256
+        Some([0x04] | [0x05] | [0x0b] | [0x01] | [0x03, 0x02]) => Some(256),
+        // sha384WithRSA, ecdsa-with-SHA384
+        Some([0x0c] | [0x03, 0x03]) => Some(384),
+        // sha512WithRSA, ecdsa-with-SHA512
+        Some([0x0d] | [0x03, 0x04]) => Some(512),
+        _ => None,
+    };
+
+    match hash {
+        Some(256) => Ok(Sha256::digest(cert).to_vec()),
+        Some(384) => Ok(Sha384::digest(cert).to_vec()),
+        Some(512) => Ok(Sha512::digest(cert).to_vec()),
+        _ => Err(TlsError::Config(format!(
+            "channel binding is not supported with server certificate signature algorithm {}",
+            der::oid_to_string(oid),
+        ))),
+    }
+}
+
+/// Returns the DER encoded `signatureAlgorithm` object identifier and its parameters of a certificate.
+///
+/// ```text
+/// Certificate ::= SEQUENCE {
+///     tbsCertificate       TBSCertificate,
+///     signatureAlgorithm   AlgorithmIdentifier,
+///     signatureValue       BIT STRING  }
+///
+/// AlgorithmIdentifier ::= SEQUENCE {
+///     algorithm   OBJECT IDENTIFIER,
+///     parameters  ANY DEFINED BY algorithm OPTIONAL  }
+/// ```
+#[cfg(feature = "tls")]
+fn signature_algorithm(cert: &[u8]) -> Option<(&[u8], &[u8])> {
+    use der::{OID, SEQUENCE, element};
+
+    let (cert, _) = element(cert, SEQUENCE)?;
+    let (_tbs, cert) = element(cert, SEQUENCE)?;
+    let (algorithm, _) = element(cert, SEQUENCE)?;
+    element(algorithm, OID)
+}
+
+/// Returns the hash algorithm object identifier of RSASSA-PSS parameters, `None` if it is the
+/// default SHA-1.
+///
+/// ```text
+/// RSASSA-PSS-params ::= SEQUENCE {
+///     hashAlgorithm      [0] HashAlgorithm DEFAULT sha1,
+///     maskGenAlgorithm   [1] MaskGenAlgorithm DEFAULT mgf1SHA1,
+///     saltLength         [2] INTEGER DEFAULT 20,
+///     trailerField       [3] TrailerField DEFAULT trailerFieldBC  }
+/// ```
+///
+/// <https://datatracker.ietf.org/doc/html/rfc4055#section-3.1>
+#[cfg(feature = "tls")]
+fn pss_hash_algorithm(params: &[u8]) -> Option<Option<&[u8]>> {
+    use der::{OID, SEQUENCE, element};
+
+    let (params, _) = element(params, SEQUENCE)?;
+    let Some((hash, _)) = element(params, 0xa0) else {
+        return Some(None);
+    };
+    let (hash, _) = element(hash, SEQUENCE)?;
+    let (oid, _) = element(hash, OID)?;
+    Some(Some(oid))
+}
+
+/// Minimal DER decoding.
+#[cfg(feature = "tls")]
+mod der {
+    pub const SEQUENCE: u8 = 0x30;
+    pub const OID: u8 = 0x06;
+
+    /// Split DER element with expected tag into its content and the rest.
+    pub fn element(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+        let (&found, der) = der.split_first()?;
+        if found != tag {
+            return None;
+        }
+        let (&len, mut der) = der.split_first()?;
+        let len = match len {
+            0..=0x7f => len as usize,
+            0x81..=0x84 => {
+                let (bytes, rest) = der.split_at_checked((len & 0x7f) as usize)?;
+                der = rest;
+                bytes.iter().fold(0, |acc, &b| acc << 8 | b as usize)
+            },
+            _ => return None,
+        };
+        der.split_at_checked(len)
+    }
+
+    /// Render object identifier in dotted decimal notation, e.g. `1.3.101.112`.
+    pub fn oid_to_string(oid: &[u8]) -> String {
+        let mut arcs = vec![];
+        let mut arc = 0u64;
+        for &byte in oid {
+            arc = arc << 7 | u64::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                arcs.push(arc);
+                arc = 0;
+            }
+        }
+        let mut output = String::new();
+        for (i, arc) in arcs.into_iter().enumerate() {
+            let arc = match i {
+                // the first byte encodes the first two arcs
+                0 => format!("{}.{}", arc.min(80) / 40, arc - arc.min(80) / 40 * 40),
+                _ => format!(".{arc}"),
+            };
+            output.push_str(&arc);
+        }
+        output
+    }
+}
+
+#[cfg(feature = "tls")]
+mod rustls {
+    use ::rustls::{
+        CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+        SupportedProtocolVersion,
+        client::{
+            WebPkiServerVerifier,
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        },
+        crypto::{CryptoProvider, ring, verify_tls12_signature, verify_tls13_signature},
+        pki_types::{
+            CertificateDer, CertificateRevocationListDer, PrivateKeyDer, ServerName, UnixTime,
+            pem::PemObject,
+        },
+    };
+    use bytes::BytesMut;
+    use std::{io, path::PathBuf, sync::Arc};
+    use tokio_rustls::TlsConnector;
+
+    use super::{ALPN_POSTGRESQL, TlsError};
+    use crate::{
+        Result,
+        connection::{Config, SslMode, SslNegotiation},
+        net::Socket,
+        postgres::{ProtocolError, frontend},
+    };
+
+    pub async fn negotiate(mut socket: Socket, config: &Config, host: &str) -> Result<Socket> {
+        let connector = connector(config)?;
+        let Ok(server_name) = ServerName::try_from(host.to_owned()) else {
+            return Err(TlsError::Config(format!("invalid server name `{host}`")).into());
+        };
+
+        if config.ssl_negotiation == SslNegotiation::Direct {
+            let socket = socket.upgrade_tls(connector, server_name).await.map_err(TlsError::Handshake)?;
+            // server that does not select the ALPN may not be a postgres server
+            if socket.alpn_protocol() != Some(ALPN_POSTGRESQL) {
+                return Err(TlsError::NoAlpn.into());
+            }
+            return Ok(socket);
+        }
+
+        let mut buf = BytesMut::with_capacity(8);
+        frontend::SslRequest.write(&mut buf);
+        std::future::poll_fn(|cx|crate::io::poll_write_all(&mut socket, &mut buf, cx)).await?;
+
+        // The server then responds with a single byte containing S or N, indicating that it is willing
+        // or unwilling to perform SSL, respectively.
+        //
+        // read exactly one byte, any data after it is not protected by SSL
+        let mut response = [0u8; 1];
+        let n = std::future::poll_fn(|cx|crate::io::poll_read(&mut socket, &mut &mut response[..], cx)).await?;
+
+        match (n, response[0]) {
+            (0, _) => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            (_, b'S') => Ok(socket.upgrade_tls(connector, server_name).await.map_err(TlsError::Handshake)?),
+            (_, b'N') if matches!(config.ssl_mode, SslMode::Prefer) => Ok(socket),
+            (_, b'N') => Err(TlsError::NotSupported.into()),
+            (_, found) => Err(ProtocolError::unexpected_phase(found, "ssl negotiation").into()),
+        }
+    }
+
+    fn connector(config: &Config) -> Result<TlsConnector, TlsError> {
+        let provider = Arc::new(ring::default_provider());
+
+        let roots = root_store(config, default_root_cert())?;
+        let verifier = verifier(config, roots, &provider)?;
+
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&protocol_versions(config)?)
+            .map_err(|err|TlsError::Config(err.to_string()))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier);
+
+        let client_cert = match config.ssl_cert_mode_disable {
+            true => None,
+            false => client_cert(config)?,
+        };
+
+        let mut tls_config = match client_cert {
+            Some((certs, key)) => builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|err|TlsError::Config(format!("invalid client certificate: {err}")))?,
+            None => builder.with_no_client_auth(),
+        };
+
+        tls_config.alpn_protocols = vec![ALPN_POSTGRESQL.to_vec()];
+        tls_config.enable_sni = config.ssl_sni;
+
+        Ok(TlsConnector::from(Arc::new(tls_config)))
+    }
+
+    /// Select server certificate verifier by sslmode and whether root certificates is available.
+    fn verifier(
+        config: &Config,
+        roots: Option<RootCertStore>,
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Arc<dyn ServerCertVerifier>, TlsError> {
+        let verifier: Arc<dyn ServerCertVerifier> = match (config.ssl_mode, roots) {
+            (SslMode::VerifyFull, Some(roots)) => webpki_verifier(roots, crls(config)?, provider)?,
+            (_, Some(roots)) => Arc::new(VerifyCa(webpki_verifier(roots, crls(config)?, provider)?)),
+            (SslMode::VerifyFull | SslMode::VerifyCa, None) => {
+                return Err(TlsError::Config(format!(
+                    "root certificate file {:?} does not exist, either provide the file, \
+                    use the system's trusted roots with `sslrootcert=system`, \
+                    or change sslmode to disable server certificate verification",
+                    default_root_cert().unwrap_or_default(),
+                )))
+            },
+            (_, None) => Arc::new(NoVerify(provider.clone())),
+        };
+        Ok(verifier)
+    }
+
+    /// TLS protocol versions within `ssl_min_protocol_version` and `ssl_max_protocol_version`.
+    ///
+    /// Only TLSv1.2 and TLSv1.3 is supported, older version is treated as TLSv1.2.
+    fn protocol_versions(config: &Config) -> Result<Vec<&'static SupportedProtocolVersion>, TlsError> {
+        let rank = |version: &str|match version {
+            "TLSv1.3" => 3,
+            "TLSv1.2" => 2,
+            _ => 1,
+        };
+        let min = config.ssl_min_protocol_version.as_deref().map_or(2, rank);
+        let max = config.ssl_max_protocol_version.as_deref().map_or(3, rank);
+
+        let versions = [(2, &::rustls::version::TLS12), (3, &::rustls::version::TLS13)]
+            .into_iter()
+            .filter(|(rank,_)|(min..=max).contains(rank))
+            .map(|(_,version)|version)
+            .collect::<Vec<_>>();
+
+        if versions.is_empty() {
+            return Err(TlsError::Config(format!(
+                "no supported TLS protocol version between {:?} and {:?}",
+                config.ssl_min_protocol_version.as_deref().unwrap_or("TLSv1.2"),
+                config.ssl_max_protocol_version.as_deref().unwrap_or("TLSv1.3"),
+            )));
+        }
+
+        Ok(versions)
+    }
+
+    /// Load certificate revocation lists from `sslcrl` and `sslcrldir`.
+    ///
+    /// If neither is set, `~/.postgresql/root.crl` is used if exists.
+    fn crls(config: &Config) -> Result<Vec<CertificateRevocationListDer<'static>>, TlsError> {
+        let mut paths = vec![];
+
+        match (config.ssl_crl.as_deref(), config.ssl_crl_dir.as_deref()) {
+            (None, None) => paths.extend(default_file("root.crl").filter(|path|path.exists())),
+            (file, dir) => {
+                paths.extend(file.map(PathBuf::from));
+                if let Some(dir) = dir {
+                    let entries = std::fs::read_dir(dir)
+                        .map_err(|err|TlsError::Config(format!("failed to read CRL directory {dir:?}: {err}")))?;
+                    for entry in entries.flatten() {
+                        if entry.file_type().is_ok_and(|e|e.is_file()) {
+                            paths.push(entry.path());
+                        }
+                    }
+                }
+            },
+        }
+
+        let mut crls = vec![];
+        for path in paths {
+            let read = CertificateRevocationListDer::pem_file_iter(&path)
+                .and_then(|crls|crls.collect::<Result<Vec<_>, _>>())
+                .map_err(|err|TlsError::Config(format!("failed to read CRL {path:?}: {err}")))?;
+            crls.extend(read);
+        }
+
+        Ok(crls)
+    }
+
+    /// Load root certificates from `sslrootcert`.
+    ///
+    /// If `sslrootcert` is not set, the `default` file, `~/.postgresql/root.crt`, is used if exists.
+    fn root_store(config: &Config, default: Option<PathBuf>) -> Result<Option<RootCertStore>, TlsError> {
+        let path = match config.ssl_root_cert.as_deref() {
+            Some("system") => {
+                return Ok(Some(RootCertStore {
+                    roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                }))
+            },
+            Some(path) => PathBuf::from(path),
+            None => match default {
+                Some(path) if path.exists() => path,
+                _ => return Ok(None),
+            },
+        };
+
+        let mut roots = RootCertStore::empty();
+        let certs = CertificateDer::pem_file_iter(&path)
+            .map_err(|err|TlsError::Config(format!("failed to read root certificate {path:?}: {err}")))?;
+
+        for cert in certs {
+            let cert = cert
+                .map_err(|err|TlsError::Config(format!("failed to read root certificate {path:?}: {err}")))?;
+            roots
+                .add(cert)
+                .map_err(|err|TlsError::Config(format!("invalid root certificate {path:?}: {err}")))?;
+        }
+
+        Ok(Some(roots))
+    }
+
+    /// Load client certificate from `sslcert` and its private key from `sslkey`.
+    ///
+    /// If not set, `~/.postgresql/postgresql.crt` and `~/.postgresql/postgresql.key` is used if exists.
+    fn client_cert(
+        config: &Config,
+    ) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>, TlsError> {
+        let cert = match config.ssl_cert.as_deref() {
+            Some(path) => PathBuf::from(path),
+            None => match default_file("postgresql.crt") {
+                Some(path) if path.exists() => path,
+                _ => return Ok(None),
+            },
+        };
+
+        let key = match config.ssl_key.as_deref() {
+            Some(path) => PathBuf::from(path),
+            None => default_file("postgresql.key").unwrap_or_default(),
+        };
+
+        let certs = CertificateDer::pem_file_iter(&cert)
+            .and_then(|certs|certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|err|TlsError::Config(format!("failed to read client certificate {cert:?}: {err}")))?;
+
+        check_key_permission(&key)?;
+
+        let key = PrivateKeyDer::from_pem_file(&key)
+            .map_err(|err|TlsError::Config(format!("failed to read private key {key:?}: {err}")))?;
+
+        Ok(Some((certs, key)))
+    }
+
+    /// Private key must not allow any access to world or group, or group read if owned by root.
+    #[cfg(unix)]
+    fn check_key_permission(path: &std::path::Path) -> Result<(), TlsError> {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = std::fs::metadata(path)
+            .map_err(|err|TlsError::Config(format!("failed to read private key {path:?}: {err}")))?;
+
+        let mask = if meta.uid() == 0 { 0o037 } else { 0o077 };
+
+        if meta.mode() & mask != 0 {
+            return Err(TlsError::Config(format!(
+                "private key file {path:?} has group or world access, \
+                file must have permissions u=rw (0600) or less if owned by the current user, \
+                or permissions u=rw,g=r (0640) or less if owned by root",
+            )));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn check_key_permission(_: &std::path::Path) -> Result<(), TlsError> {
+        Ok(())
+    }
+
+    fn default_root_cert() -> Option<PathBuf> {
+        default_file("root.crt")
+    }
+
+    fn default_file(name: &str) -> Option<PathBuf> {
+        std::env::home_dir().map(|home|home.join(".postgresql").join(name))
+    }
+
+    fn webpki_verifier(
+        roots: RootCertStore,
+        crls: Vec<CertificateRevocationListDer<'static>>,
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Arc<WebPkiServerVerifier>, TlsError> {
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .with_crls(crls)
+            .build()
+            .map_err(|err|TlsError::Config(err.to_string()))
+    }
+
+    /// Verify certificate chain, but not the server host name.
+    #[derive(Debug)]
+    struct VerifyCa(Arc<WebPkiServerVerifier>);
+
+    impl ServerCertVerifier for VerifyCa {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            intermediates: &[CertificateDer<'_>],
+            server_name: &ServerName<'_>,
+            ocsp_response: &[u8],
+            now: UnixTime,
+        ) -> Result<ServerCertVerified, ::rustls::Error> {
+            let result = self.0.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now);
+            match result {
+                // host name is verified after the certificate chain
+                Err(::rustls::Error::InvalidCertificate(
+                    CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+                )) => Ok(ServerCertVerified::assertion()),
+                result => result,
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, ::rustls::Error> {
+            self.0.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, ::rustls::Error> {
+            self.0.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.supported_verify_schemes()
+        }
+    }
+
+    /// Does not verify server certificate, but still verify handshake signatures.
+    #[derive(Debug)]
+    struct NoVerify(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for NoVerify {
+        fn verify_server_cert(
+            &self,
+            _: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &ServerName<'_>,
+            _: &[u8],
+            _: UnixTime,
+        ) -> Result<ServerCertVerified, ::rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, ::rustls::Error> {
+            verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, ::rustls::Error> {
+            verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        const ROOT_CRT: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/root.crt"));
+
+        #[test]
+        fn root_cert_lookup() {
+            let dir = std::env::temp_dir().join(format!("postro-root-cert-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let default = dir.join("root.crt");
+
+            // missing default file disables verification
+            assert!(root_store(&Config::new(), Some(default.clone())).unwrap().is_none());
+            assert!(root_store(&Config::new(), None).is_ok());
+
+            std::fs::write(&default, ROOT_CRT).unwrap();
+            assert_eq!(root_store(&Config::new(), Some(default.clone())).unwrap().unwrap().len(), 1);
+
+            // explicit `sslrootcert` must exist
+            let missing = dir.join("missing.crt").to_str().unwrap().to_owned();
+            assert!(root_store(&Config::new().with_ssl_root_cert(missing), Some(default.clone())).is_err());
+
+            let system = root_store(&Config::new().with_ssl_root_cert("system"), None).unwrap().unwrap();
+            assert!(!system.is_empty());
+
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn sslmode_verifier() {
+            let provider = Arc::new(ring::default_provider());
+            let roots = || {
+                let mut roots = RootCertStore::empty();
+                roots.add(CertificateDer::from_pem_slice(ROOT_CRT).unwrap()).unwrap();
+                roots
+            };
+
+            for (mode, has_roots, expected) in [
+                (SslMode::Prefer, false, Some("NoVerify")),
+                (SslMode::Require, false, Some("NoVerify")),
+                (SslMode::VerifyCa, false, None),
+                (SslMode::VerifyFull, false, None),
+                (SslMode::Prefer, true, Some("VerifyCa")),
+                (SslMode::Require, true, Some("VerifyCa")),
+                (SslMode::VerifyCa, true, Some("VerifyCa")),
+                (SslMode::VerifyFull, true, Some("WebPkiServerVerifier")),
+            ] {
+                let config = Config::new().with_ssl_mode(mode);
+                let result = verifier(&config, has_roots.then(roots), &provider);
+                match expected {
+                    Some(expected) => assert!(format!("{:?}", result.unwrap()).starts_with(expected), "{mode:?}"),
+                    None => assert!(matches!(result, Err(TlsError::Config(_))), "{mode:?}"),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for TlsError { }
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disabled => f.write_str("SSL is required, but `tls` feature is not enabled"),
+            Self::NotSupported => f.write_str("server does not support SSL, but SSL was required"),
+            Self::Config(msg) => f.write_str(msg),
+            Self::Handshake(err) => write!(f, "SSL handshake failed: {err}"),
+            Self::WeakSslMode(mode) => write!(
+                f,
+                "weak sslmode `{}` may not be used with sslnegotiation=direct \
+                (use `require`, `verify-ca`, or `verify-full`)",
+                mode.as_str(),
+            ),
+            Self::NoAlpn => f.write_str("direct SSL connection was established without ALPN protocol negotiation"),
+        }
+    }
+}
+
+impl fmt::Debug for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{Shutdown, TcpListener},
+        thread,
+    };
+
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[cfg(feature = "tls")]
+    const SSL_REQUEST: &[u8] = &[0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f];
+
+    /// Negotiate with a server which reply `response` then close the connection.
+    ///
+    /// Returns whether the socket is TLS, and the bytes received by the server.
+    fn negotiate_with(config: &Config, response: &'static [u8]) -> (Result<bool>, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // the client may have closed the connection on local error
+            let _ = stream.write_all(response);
+            let _ = stream.shutdown(Shutdown::Write);
+            let mut received = vec![];
+            let _ = stream.read_to_end(&mut received);
+            received
+        });
+
+        (connect(config, port), server.join().unwrap())
+    }
+
+    /// Negotiate to local server at `port`, returns whether the socket is TLS.
+    fn connect(config: &Config, port: u16) -> Result<bool> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let socket = Socket::connect_tcp("127.0.0.1", port, config).await?;
+            negotiate(socket, config, "localhost").await.map(|socket|socket.is_tls())
+        })
+    }
+
+    /// Direct SSL negotiation with a TLS server which support given ALPN protocols.
+    #[cfg(feature = "tls")]
+    fn negotiate_direct(alpn: &[&[u8]]) -> Result<bool> {
+        use ::rustls::{
+            ServerConfig, ServerConnection,
+            crypto::ring,
+            pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        };
+        use std::sync::Arc;
+
+        let cert = CertificateDer::from_pem_slice(include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/server.crt")));
+        let key = PrivateKeyDer::from_pem_slice(include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/server.key")));
+        let mut server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.unwrap()], key.unwrap())
+            .unwrap();
+        server_config.alpn_protocols = alpn.iter().map(|e|e.to_vec()).collect();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut conn = ServerConnection::new(Arc::new(server_config)).unwrap();
+            while conn.is_handshaking() && conn.complete_io(&mut stream).is_ok() { }
+        });
+
+        let config = Config::new()
+            .with_ssl_negotiation(SslNegotiation::Direct)
+            .with_ssl_mode(SslMode::VerifyFull)
+            .with_ssl_root_cert(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/root.crt"));
+        let result = connect(&config, port);
+        server.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn sslmode_disable() {
+        let (result, received) = negotiate_with(&Config::new().with_ssl_mode(SslMode::Disable), b"");
+        assert!(!result.unwrap());
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn direct_weak_sslmode() {
+        for mode in [SslMode::Disable, SslMode::Prefer] {
+            let config = Config::new().with_ssl_mode(mode).with_ssl_negotiation(SslNegotiation::Direct);
+            let (result, received) = negotiate_with(&config, b"");
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::WeakSslMode(m)) if *m == mode));
+            assert!(received.is_empty());
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn direct_alpn() {
+        assert!(negotiate_direct(&[ALPN_POSTGRESQL]).unwrap());
+
+        // server does not select any protocol
+        let result = negotiate_direct(&[]);
+        assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::NoAlpn)));
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn sslmode_without_tls() {
+        let (result, received) = negotiate_with(&Config::new().with_ssl_mode(SslMode::Prefer), b"");
+        assert!(!result.unwrap());
+        assert!(received.is_empty());
+
+        let (result, _) = negotiate_with(&Config::new().with_ssl_mode(SslMode::Require), b"");
+        assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::Disabled)));
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn sslmode_prefer() {
+        // server does not support SSL
+        let (result, received) = negotiate_with(&Config::new().with_ssl_mode(SslMode::Prefer), b"N");
+        assert!(!result.unwrap());
+        assert_eq!(received, SSL_REQUEST);
+
+        // the connection is retried without SSL
+        let (result, _) = negotiate_with(&Config::new().with_ssl_mode(SslMode::Prefer), b"S");
+        assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::Handshake(_))));
+
+        // local config error is not retried
+        let config = Config::new().with_ssl_mode(SslMode::Prefer).with_ssl_root_cert("/nonexistent/root.crt");
+        let (result, received) = negotiate_with(&config, b"S");
+        assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::Config(_))));
+        assert!(received.is_empty());
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn sslmode_require() {
+        for mode in [SslMode::Require, SslMode::VerifyCa, SslMode::VerifyFull] {
+            let config = Config::new().with_ssl_mode(mode).with_ssl_root_cert("system");
+            let (result, received) = negotiate_with(&config, b"N");
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::NotSupported)));
+            assert_eq!(received, SSL_REQUEST);
+
+            let (result, _) = negotiate_with(&config, b"S");
+            assert!(matches!(result.unwrap_err().kind(), ErrorKind::Tls(TlsError::Handshake(_))));
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn server_end_point() {
+        use ::rustls::pki_types::{CertificateDer, pem::PemObject};
+        use sha2::{Digest, Sha256, Sha384, Sha512};
+
+        macro_rules! cert {
+            ($name:literal) => {
+                CertificateDer::from_pem_slice(include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/", $name)))
+                    .unwrap()
+                    .to_vec()
+            };
+        }
+
+        let cert = cert!("root.crt");
+        assert_eq!(tls_server_end_point(&cert).unwrap(), Sha256::digest(&cert).to_vec());
+
+        let cert = cert!("ecdsa-sha384.crt");
+        assert_eq!(tls_server_end_point(&cert).unwrap(), Sha384::digest(&cert).to_vec());
+
+        let cert = cert!("rsa-sha512.crt");
+        assert_eq!(tls_server_end_point(&cert).unwrap(), Sha512::digest(&cert).to_vec());
+
+        let cert = cert!("rsa-pss-sha384.crt");
+        assert_eq!(tls_server_end_point(&cert).unwrap(), Sha384::digest(&cert).to_vec());
+
+        let cert = cert!("ed25519.crt");
+        assert_eq!(
+            tls_server_end_point(&cert).unwrap_err().to_string(),
+            "channel binding is not supported with server certificate signature algorithm 1.3.101.112",
+        );
+
+        assert!(tls_server_end_point(b"not a certificate").is_err());
+
+        // RSASSA-PSS with all default parameters, SHA-1
+        assert_eq!(pss_hash_algorithm(&[0x30, 0x00]), Some(None));
+        assert_eq!(pss_hash_algorithm(&[0x05, 0x00]), None);
+    }
+}
@@ -1,14 +1,17 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt};
 
 use crate::{
     Result,
     common::unit_error,
+    connection::{ChannelBinding, Credential, CredentialProvider},
     executor::Executor,
-    postgres::{BackendMessage, backend, frontend},
+    postgres::{BackendMessage, ProtocolError, ProtocolVersion, backend, frontend},
     transaction::Transaction,
     transport::{PgTransport, PgTransportExt},
 };
 
+mod scram;
+
 /// Config for postgres startup phase.
 ///
 /// <https://www.postgresql.org/docs/current/protocol-flow.html#PROTOCOL-FLOW-START-UP>
@@ -16,7 +19,14 @@ pub struct StartupConfig<'a> {
     pub(crate) user: Cow<'a,str>,
     pub(crate) database: Option<Cow<'a,str>>,
     pub(crate) password: Option<Cow<'a,str>>,
+    pub(crate) credential: Option<Credential>,
     pub(crate) replication: Option<Cow<'a,str>>,
+    pub(crate) options: Option<Cow<'a,str>>,
+    pub(crate) params: Vec<(Cow<'a,str>,Cow<'a,str>)>,
+    pub(crate) channel_binding: ChannelBinding,
+    pub(crate) tls_server_end_point: Option<Cow<'a,[u8]>>,
+    pub(crate) min_protocol_version: ProtocolVersion,
+    pub(crate) max_protocol_version: ProtocolVersion,
 }
 
 /// Startup phase successful response.
@@ -24,6 +34,8 @@ pub struct StartupResponse {
     /// This message provides secret-key data that the frontend must
     /// save if it wants to be able to issue cancel requests later.
     pub backend_key_data: backend::BackendKeyData,
+    /// The protocol version negotiated with the server.
+    pub protocol_version: ProtocolVersion,
 }
 
 unit_error! {
@@ -32,6 +44,20 @@ unit_error! {
     pub struct UnsupportedAuth("auth method is not yet supported");
 }
 
+/// An error when authentication exchange with postgres failed.
+pub enum AuthError {
+    /// Postgres does not offer any supported SASL mechanism.
+    UnsupportedMechanism,
+    /// Postgres sent malformed or unexpected authentication message.
+    InvalidMessage(&'static str),
+    /// Postgres reported an error in the SASL exchange.
+    Server(String),
+    /// Server signature does not match, postgres does not prove that it knows the password.
+    SignatureMismatch,
+    /// Channel binding is required, but postgres authenticated without channel binding.
+    ChannelBindingRequired,
+}
+
 /// Perform a startup message.
 ///
 /// <https://www.postgresql.org/docs/17/protocol-flow.html#PROTOCOL-FLOW-START-UP>
@@ -46,13 +72,20 @@ pub async fn startup<'a, IO: PgTransport>(
 
     // (Optionally, the startup message can include additional settings for run-time parameters.)
 
+    let params = opt.runtime_params().collect::<Vec<_>>();
+
     io.send_startup(frontend::Startup {
         user: opt.user(),
         database: opt.database(),
         replication: opt.replication(),
+        options: opt.options(),
+        params: &params,
+        version: opt.max_protocol_version,
     });
     io.flush().await?;
 
+    let mut protocol_version = opt.max_protocol_version;
+
     // The server then sends an appropriate authentication request message,
     // to which the frontend must reply with an appropriate authentication response message (such as a password).
     //
@@ -60,16 +93,92 @@ pub async fn startup<'a, IO: PgTransport>(
     // In some methods, no response at all is needed from the frontend, and so no authentication request occurs.
     // For GSSAPI, SSPI and SASL, multiple exchanges of packets may be needed to complete the authentication.
 
+    let mut scram = None::<scram::Scram>;
+
     loop {
         use backend::Authentication::*;
-        match io.recv().await? {
+        let auth = match io.recv().await? {
+            BackendMessage::Authentication(auth) => auth,
+            BackendMessage::NegotiateProtocolVersion(negotiate) => {
+                protocol_version = negotiate_version(&opt, negotiate)?;
+                continue;
+            },
+            f => Err(f.unexpected("authentication phase"))?,
+        };
+        match auth {
             // we gucci
-            Ok => break,
+            Ok => {
+                // do not let postgres skip the server signature verification
+                if scram.as_ref().is_some_and(|e|!e.is_verified()) {
+                    return Err(AuthError::InvalidMessage("SASL exchange is not completed").into());
+                }
+                if opt.channel_binding == ChannelBinding::Require
+                    && !scram.is_some_and(|e|e.is_channel_bound())
+                {
+                    return Err(AuthError::ChannelBindingRequired.into());
+                }
+                break
+            },
             // The frontend must now send a PasswordMessage containing the password in clear-text form.
             CleartextPassword => {
-                io.send(frontend::PasswordMessage { password: opt.password().unwrap_or_default() });
+                if opt.channel_binding == ChannelBinding::Require {
+                    return Err(AuthError::ChannelBindingRequired.into());
+                }
+                let password = password(&opt).await?;
+                io.send(frontend::PasswordMessage { password: &password });
+                io.flush().await?;
+            },
+            // The frontend must now send a PasswordMessage containing the password (with user name) encrypted via MD5,
+            // then encrypted again using the 4-byte random salt specified in the AuthenticationMD5Password message.
+            MD5Password { salt } => {
+                if opt.channel_binding == ChannelBinding::Require {
+                    return Err(AuthError::ChannelBindingRequired.into());
+                }
+                let password = md5_password(opt.user(), &password(&opt).await?, salt);
+                io.send(frontend::PasswordMessage { password: &password });
+                io.flush().await?;
+            },
+            // The frontend must now initiate a SASL negotiation, using one of the SASL mechanisms listed in the message.
+            SASL { name } => {
+                use scram::{Binding, SCRAM_SHA_256, SCRAM_SHA_256_PLUS, has_mechanism};
+
+                let plus = has_mechanism(&name, SCRAM_SHA_256_PLUS);
+                let (mechanism, binding) = match (opt.channel_binding, opt.tls_server_end_point(), plus) {
+                    (ChannelBinding::Disable, _, _) => (SCRAM_SHA_256, Binding::Unsupported),
+                    (_, Some(data), true) => (SCRAM_SHA_256_PLUS, Binding::TlsServerEndPoint(data)),
+                    (ChannelBinding::Require, _, _) => return Err(AuthError::ChannelBindingRequired.into()),
+                    // client support channel binding, but server does not
+                    (_, Some(_), false) => (SCRAM_SHA_256, Binding::NotUsed),
+                    (_, None, _) => (SCRAM_SHA_256, Binding::Unsupported),
+                };
+
+                if !has_mechanism(&name, mechanism) {
+                    return Err(AuthError::UnsupportedMechanism.into());
+                }
+
+                let password = password(&opt).await?;
+                let sasl = scram.insert(scram::Scram::new(&password, binding));
+                io.send(frontend::SASLInitialResponse {
+                    mechanism,
+                    data: sasl.client_first().as_bytes(),
+                });
+                io.flush().await?;
+            },
+            // The frontend must respond with a SASLResponse message.
+            SASLContinue { data } => {
+                let Some(sasl) = scram.as_mut() else {
+                    return Err(AuthError::InvalidMessage("unexpected SASL continue").into());
+                };
+                io.send(frontend::SASLResponse { data: sasl.client_final(&data)?.as_bytes() });
                 io.flush().await?;
             },
+            // SASL authentication has completed with additional mechanism-specific data for the client.
+            SASLFinal { data } => {
+                let Some(sasl) = scram.as_mut() else {
+                    return Err(AuthError::InvalidMessage("unexpected SASL final").into());
+                };
+                sasl.verify(&data)?;
+            },
             // TODO: support more authentication method
             _ => return Err(UnsupportedAuth.into())
         }
@@ -92,6 +201,7 @@ pub async fn startup<'a, IO: PgTransport>(
         match io.recv().await? {
             ReadyForQuery(_) => break,
             BackendKeyData(new_key_data) => key_data = Some(new_key_data),
+            NegotiateProtocolVersion(negotiate) => protocol_version = negotiate_version(&opt, negotiate)?,
             // NOTE: ParameterStatus will get eaten by the IO
             f => Err(f.unexpected("startup phase"))?,
         }
@@ -99,9 +209,51 @@ pub async fn startup<'a, IO: PgTransport>(
 
     Ok(StartupResponse {
         backend_key_data: key_data.expect("postgres never send backend key data"),
+        protocol_version,
     })
 }
 
+/// Returns password from the credential provider if any, otherwise the static password.
+async fn password<'s>(opt: &'s StartupConfig<'_>) -> Result<Cow<'s, str>> {
+    match &opt.credential {
+        Some(credential) => Ok(credential.0.password().await?.into()),
+        None => Ok(opt.password().unwrap_or_default().into()),
+    }
+}
+
+/// Handle server protocol version downgrade.
+fn negotiate_version(
+    opt: &StartupConfig,
+    negotiate: backend::NegotiateProtocolVersion,
+) -> Result<ProtocolVersion, ProtocolError> {
+    // postgres actually sends the full protocol version number, not just the minor version
+    let version = ProtocolVersion::new((negotiate.minor >> 16) as u16, negotiate.minor as u16);
+    if version < opt.min_protocol_version || version > opt.max_protocol_version {
+        return Err(ProtocolError::UnsupportedVersion { min: opt.min_protocol_version, found: version });
+    }
+    Ok(version)
+}
+
+/// Returns `concat('md5', md5(concat(md5(concat(password, username)), random-salt)))`.
+fn md5_password(user: &str, password: &str, salt: [u8; 4]) -> String {
+    use md5::{Digest, Md5};
+    use std::fmt::Write;
+
+    fn hex(digest: impl AsRef<[u8]>, buf: &mut String) {
+        for b in digest.as_ref() {
+            write!(buf, "{b:02x}").unwrap();
+        }
+    }
+
+    let mut inner = String::with_capacity(32);
+    hex(Md5::new().chain_update(password).chain_update(user).finalize(), &mut inner);
+
+    let mut output = String::with_capacity(35);
+    output.push_str("md5");
+    hex(Md5::new().chain_update(inner).chain_update(salt).finalize(), &mut output);
+    output
+}
+
 /// Begin transaction with given executor.
 pub async fn begin<Exec: Executor>(exec: Exec) -> Result<Transaction<Exec::Transport>> {
     let mut io = exec.connection().await?;
@@ -116,7 +268,19 @@ pub async fn begin<Exec: Executor>(exec: Exec) -> Result<Transaction<Exec::Trans
 impl<'a> StartupConfig<'a> {
     /// Create new config, the database user name is required.
     pub fn new(user: impl Into<Cow<'a, str>>) -> Self {
-        Self { user: user.into(), database: None, password: None, replication: None  }
+        Self {
+            user: user.into(),
+            database: None,
+            password: None,
+            credential: None,
+            replication: None,
+            options: None,
+            params: Vec::new(),
+            channel_binding: ChannelBinding::default(),
+            tls_server_end_point: None,
+            min_protocol_version: ProtocolVersion::V3_0,
+            max_protocol_version: ProtocolVersion::V3_0,
+        }
     }
 
     /// The database user name to connect as.
@@ -144,6 +308,13 @@ impl<'a> StartupConfig<'a> {
         self.password = Some(password.into());
     }
 
+    /// Set the [`CredentialProvider`] that is called when server request password authentication.
+    ///
+    /// The provided password takes precedence over [`password`][StartupConfig::password].
+    pub fn set_credential_provider(&mut self, provider: impl CredentialProvider) {
+        self.credential = Some(Credential(std::sync::Arc::new(provider)));
+    }
+
     /// Used to connect in streaming replication mode, where a small set of replication commands can be issued
     /// instead of SQL statements.
     ///
@@ -163,4 +334,269 @@ impl<'a> StartupConfig<'a> {
     pub fn set_replication(&mut self, replication: impl Into<Cow<'a,str>>) {
         self.replication = Some(replication.into());
     }
+
+    /// Command-line options to send to the server at connection start, e.g. `-c search_path=app`.
+    ///
+    /// Spaces within this string are considered to separate arguments,
+    /// unless escaped with a backslash (`\`).
+    pub fn options(&self) -> Option<&str> {
+        self.options.as_ref().map(<_>::as_ref)
+    }
+
+    /// Command-line options to send to the server at connection start, e.g. `-c search_path=app`.
+    ///
+    /// Spaces within this string are considered to separate arguments,
+    /// unless escaped with a backslash (`\`).
+    pub fn set_options(&mut self, options: impl Into<Cow<'a,str>>) {
+        self.options = Some(options.into());
+    }
+
+    /// Value for the `application_name` configuration parameter.
+    pub fn application_name(&self) -> Option<&str> {
+        self.runtime_param("application_name")
+    }
+
+    /// Value for the `application_name` configuration parameter.
+    pub fn set_application_name(&mut self, name: impl Into<Cow<'a,str>>) {
+        self.set_runtime_param("application_name", name);
+    }
+
+    /// Run-time parameter to be set at backend start time, e.g. `TimeZone` or `client_encoding`.
+    pub fn runtime_param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key,_)|key == name).map(|(_,value)|value.as_ref())
+    }
+
+    /// Run-time parameter to be set at backend start time, e.g. `TimeZone` or `client_encoding`.
+    ///
+    /// Setting already existing parameter will replace the previous value.
+    pub fn set_runtime_param(&mut self, name: impl Into<Cow<'a,str>>, value: impl Into<Cow<'a,str>>) {
+        let name = name.into();
+        let value = value.into();
+        match self.params.iter_mut().find(|(key,_)|*key == name) {
+            Some((_,old)) => *old = value,
+            None => self.params.push((name, value)),
+        }
+    }
+
+    /// Returns iterator of all run-time parameters.
+    pub fn runtime_params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(name,value)|(name.as_ref(), value.as_ref()))
+    }
+
+    /// Controls the client's use of channel binding, the default is [`ChannelBinding::Prefer`].
+    pub fn channel_binding(&self) -> ChannelBinding {
+        self.channel_binding
+    }
+
+    /// Controls the client's use of channel binding, the default is [`ChannelBinding::Prefer`].
+    pub fn set_channel_binding(&mut self, channel_binding: ChannelBinding) {
+        self.channel_binding = channel_binding;
+    }
+
+    /// `tls-server-end-point` channel binding data of the underlying SSL connection.
+    ///
+    /// This is the hash of the server certificate, see [RFC 5929](https://datatracker.ietf.org/doc/html/rfc5929#section-4.1).
+    pub fn tls_server_end_point(&self) -> Option<&[u8]> {
+        self.tls_server_end_point.as_ref().map(<_>::as_ref)
+    }
+
+    /// `tls-server-end-point` channel binding data of the underlying SSL connection.
+    ///
+    /// This is the hash of the server certificate, see [RFC 5929](https://datatracker.ietf.org/doc/html/rfc5929#section-4.1).
+    pub fn set_tls_server_end_point(&mut self, data: impl Into<Cow<'a,[u8]>>) {
+        self.tls_server_end_point = Some(data.into());
+    }
+
+    /// The minimum protocol version to allow, the default is [`ProtocolVersion::V3_0`].
+    pub fn min_protocol_version(&self) -> ProtocolVersion {
+        self.min_protocol_version
+    }
+
+    /// The minimum protocol version to allow, the default is [`ProtocolVersion::V3_0`].
+    ///
+    /// If the server downgrade to lower version, startup will fails.
+    pub fn set_min_protocol_version(&mut self, version: ProtocolVersion) {
+        self.min_protocol_version = version;
+    }
+
+    /// The protocol version to request, the default is [`ProtocolVersion::V3_0`].
+    pub fn max_protocol_version(&self) -> ProtocolVersion {
+        self.max_protocol_version
+    }
+
+    /// The protocol version to request, the default is [`ProtocolVersion::V3_0`].
+    ///
+    /// Server may downgrade to lower minor version that it does support.
+    pub fn set_max_protocol_version(&mut self, version: ProtocolVersion) {
+        self.max_protocol_version = version;
+    }
+}
+
+impl std::error::Error for AuthError { }
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedMechanism => f.write_str("no supported SASL authentication mechanism"),
+            Self::InvalidMessage(msg) => write!(f, "invalid authentication message: {msg}"),
+            Self::Server(msg) => write!(f, "SASL authentication failed: {msg}"),
+            Self::SignatureMismatch => f.write_str("SASL server signature mismatch"),
+            Self::ChannelBindingRequired => {
+                f.write_str("channel binding required, but server authenticated client without channel binding")
+            },
+        }
+    }
+}
+
+impl fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{self}\"")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::test::{ScriptedTransport, block_on};
+
+    #[test]
+    fn md5_auth() {
+        let mut io = ScriptedTransport::new();
+        io.push(b'R', [&5u32.to_be_bytes()[..], &[1, 2, 3, 4]].concat());
+        io.push(b'R', 0u32.to_be_bytes().to_vec());
+        io.push(b'K', [42u32.to_be_bytes(), 7u32.to_be_bytes()].concat());
+        io.push(b'Z', &b"I"[..]);
+
+        let mut opt = StartupConfig::new("md5u");
+        opt.set_password("md5pass");
+
+        let res = block_on(startup(opt, &mut io)).unwrap();
+        assert_eq!(res.backend_key_data.process_id, 42);
+
+        let (msgtype, body) = io.sent().pop().unwrap();
+        assert_eq!(msgtype, b'p');
+        assert_eq!(&body[..], b"md5def0180b872ee303075dada481d544e9\0");
+    }
+
+    #[test]
+    fn credential_provider() {
+        let mut io = ScriptedTransport::new();
+        io.push(b'R', [&5u32.to_be_bytes()[..], &[1, 2, 3, 4]].concat());
+        io.push(b'R', 0u32.to_be_bytes().to_vec());
+        io.push(b'K', [42u32.to_be_bytes(), 7u32.to_be_bytes()].concat());
+        io.push(b'Z', &b"I"[..]);
+
+        let mut opt = StartupConfig::new("md5u");
+        opt.set_password("stale");
+        opt.set_credential_provider(||async { Ok::<_, std::io::Error>("md5pass".to_owned()) });
+
+        block_on(startup(opt, &mut io)).unwrap();
+
+        let (_, body) = io.sent().pop().unwrap();
+        assert_eq!(&body[..], b"md5def0180b872ee303075dada481d544e9\0");
+    }
+
+    #[test]
+    fn protocol_downgrade() {
+        let negotiate = [ProtocolVersion::V3_0.as_u32().to_be_bytes(), 0u32.to_be_bytes()].concat();
+        let secret_key = [7u8; 32];
+
+        let mut io = ScriptedTransport::new();
+        io.push(b'v', negotiate.clone());
+        io.push(b'R', 0u32.to_be_bytes().to_vec());
+        io.push(b'K', [&42u32.to_be_bytes()[..], &secret_key].concat());
+        io.push(b'Z', &b"I"[..]);
+
+        let mut opt = StartupConfig::new("postgres");
+        opt.set_max_protocol_version(ProtocolVersion::V3_2);
+
+        let res = block_on(startup(opt, &mut io)).unwrap();
+        assert_eq!(res.protocol_version, ProtocolVersion::V3_0);
+        assert_eq!(&res.backend_key_data.secret_key[..], &secret_key);
+
+        let mut io = ScriptedTransport::new();
+        io.push(b'v', negotiate);
+
+        let mut opt = StartupConfig::new("postgres");
+        opt.set_min_protocol_version(ProtocolVersion::V3_2);
+        opt.set_max_protocol_version(ProtocolVersion::V3_2);
+
+        assert!(block_on(startup(opt, &mut io)).is_err());
+    }
+
+    /// Returns the mechanism and GS2 header of the `SASLInitialResponse` sent for `offered` mechanisms.
+    fn sasl_initial(offered: &[&str], channel_binding: ChannelBinding, end_point: bool) -> (String, String) {
+        let mut mechanisms = 10u32.to_be_bytes().to_vec();
+        for mechanism in offered {
+            mechanisms.extend_from_slice(mechanism.as_bytes());
+            mechanisms.push(0);
+        }
+        mechanisms.push(0);
+
+        let mut io = ScriptedTransport::new();
+        io.push(b'R', mechanisms);
+        io.push(b'E', &b"SERROR\0C28P01\0Mstop\0\0"[..]);
+
+        let mut opt = StartupConfig::new("user");
+        opt.set_password("pencil");
+        opt.set_channel_binding(channel_binding);
+        if end_point {
+            opt.set_tls_server_end_point(&[7u8; 32][..]);
+        }
+        assert!(block_on(startup(opt, &mut io)).is_err());
+
+        let (msgtype, body) = io.sent().pop().unwrap();
+        assert_eq!(msgtype, b'p');
+        let (mechanism, data) = body.split_at(body.iter().position(|&e|e == 0).unwrap());
+        let data = std::str::from_utf8(&data[5..]).unwrap();
+        let header = data[..data.find(",n=").unwrap()].to_owned();
+        (String::from_utf8(mechanism.to_vec()).unwrap(), header)
+    }
+
+    #[test]
+    fn sasl_mechanism() {
+        use ChannelBinding::*;
+        let both = &["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256"][..];
+        let plain = &["SCRAM-SHA-256"][..];
+
+        // (offered, channel_binding, end point data, mechanism, gs2 header)
+        for (offered, channel_binding, end_point, mechanism, header) in [
+            (both, Prefer, true, "SCRAM-SHA-256-PLUS", "p=tls-server-end-point,"),
+            (both, Require, true, "SCRAM-SHA-256-PLUS", "p=tls-server-end-point,"),
+            (both, Disable, true, "SCRAM-SHA-256", "n,"),
+            (both, Prefer, false, "SCRAM-SHA-256", "n,"),
+            // client support channel binding, but server does not offer it
+            (plain, Prefer, true, "SCRAM-SHA-256", "y,"),
+            (plain, Prefer, false, "SCRAM-SHA-256", "n,"),
+        ] {
+            let sent = sasl_initial(offered, channel_binding, end_point);
+            assert_eq!(sent, (mechanism.to_owned(), header.to_owned()), "{offered:?} {channel_binding:?} {end_point}");
+        }
+    }
+
+    #[test]
+    fn channel_binding_required() {
+        let plain = 10u32.to_be_bytes().into_iter().chain(*b"SCRAM-SHA-256\0\0").collect::<Vec<_>>();
+        let md5 = [&5u32.to_be_bytes()[..], &[1, 2, 3, 4]].concat();
+        let trust = 0u32.to_be_bytes().to_vec();
+
+        for auth in [plain, md5, trust] {
+            let mut io = ScriptedTransport::new();
+            io.push(b'R', auth.clone());
+            io.push(b'K', [42u32.to_be_bytes(), 7u32.to_be_bytes()].concat());
+            io.push(b'Z', &b"I"[..]);
+
+            let mut opt = StartupConfig::new("user");
+            opt.set_password("pencil");
+            opt.set_channel_binding(ChannelBinding::Require);
+            opt.set_tls_server_end_point(&[7u8; 32][..]);
+
+            let Err(err) = block_on(startup(opt, &mut io)) else {
+                panic!("channel binding is not required for {auth:?}");
+            };
+            assert!(matches!(err.kind(), crate::error::ErrorKind::Auth(AuthError::ChannelBindingRequired)), "{auth:?}");
+            // nothing is sent after startup message
+            assert!(io.sent().is_empty(), "{auth:?}");
+        }
+    }
 }
@@ -0,0 +1,282 @@
+//! SCRAM-SHA-256 authentication.
+//!
+//! <https://www.postgresql.org/docs/current/sasl-authentication.html>
+use base64::{Engine, prelude::BASE64_STANDARD};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::AuthError;
+
+/// SASL mechanism name of SCRAM-SHA-256.
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+
+/// SASL mechanism name of SCRAM-SHA-256 with channel binding.
+pub const SCRAM_SHA_256_PLUS: &str = "SCRAM-SHA-256-PLUS";
+
+const NONCE_LEN: usize = 18;
+
+/// SCRAM-SHA-256 client state.
+pub struct Scram {
+    password: Vec<u8>,
+    gs2_header: &'static str,
+    cbind_data: Vec<u8>,
+    client_nonce: String,
+    client_first_bare: String,
+    state: State,
+}
+
+/// Channel binding usage.
+pub enum Binding<'a> {
+    /// Client does not support channel binding.
+    Unsupported,
+    /// Client support channel binding, but server does not.
+    NotUsed,
+    /// Use `tls-server-end-point` channel binding with given data.
+    TlsServerEndPoint(&'a [u8]),
+}
+
+enum State {
+    /// `client-first-message` sent, waiting for `server-first-message`.
+    ClientFirst,
+    /// `client-final-message` sent, waiting for `server-final-message`.
+    ClientFinal {
+        server_signature: [u8; 32],
+    },
+    /// `server-final-message` verified.
+    Verified,
+}
+
+impl Scram {
+    /// Create new exchange with random nonce.
+    pub fn new(password: &str, binding: Binding) -> Scram {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut nonce).expect("failed to generate random nonce");
+        Self::with_nonce(password, binding, &BASE64_STANDARD.encode(nonce))
+    }
+
+    fn with_nonce(password: &str, binding: Binding, nonce: &str) -> Scram {
+        let (gs2_header, cbind_data) = match binding {
+            Binding::Unsupported => ("n,,", vec![]),
+            Binding::NotUsed => ("y,,", vec![]),
+            Binding::TlsServerEndPoint(data) => ("p=tls-server-end-point,,", data.to_vec()),
+        };
+        Self {
+            password: saslprep(password).into_bytes(),
+            gs2_header,
+            cbind_data,
+            client_nonce: nonce.into(),
+            // postgres ignore the username in `client-first-message`,
+            // the one sent in startup message is used instead
+            client_first_bare: format!("n=,r={nonce}"),
+            state: State::ClientFirst,
+        }
+    }
+
+    /// The `client-first-message` as SASL initial response.
+    pub fn client_first(&self) -> String {
+        format!("{}{}", self.gs2_header, self.client_first_bare)
+    }
+
+    /// Process `server-first-message`, returns the `client-final-message`.
+    pub fn client_final(&mut self, server_first: &[u8]) -> Result<String, AuthError> {
+        let State::ClientFirst = self.state else {
+            return Err(AuthError::InvalidMessage("unexpected SASL continue"));
+        };
+
+        let server_first = std::str::from_utf8(server_first)
+            .map_err(|_|AuthError::InvalidMessage("non utf8 server-first-message"))?;
+
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iteration = None;
+
+        for attr in server_first.split(',') {
+            match attr.split_at_checked(2) {
+                Some(("r=", value)) => nonce = Some(value),
+                Some(("s=", value)) => salt = Some(value),
+                Some(("i=", value)) => iteration = Some(value),
+                _ => {}
+            }
+        }
+
+        let (Some(nonce), Some(salt), Some(iteration)) = (nonce, salt, iteration) else {
+            return Err(AuthError::InvalidMessage("incomplete server-first-message"));
+        };
+
+        // server nonce must starts with the client nonce
+        if nonce.len() <= self.client_nonce.len() || !nonce.starts_with(&self.client_nonce) {
+            return Err(AuthError::InvalidMessage("invalid server nonce"));
+        }
+
+        let Ok(salt) = BASE64_STANDARD.decode(salt) else {
+            return Err(AuthError::InvalidMessage("invalid salt"));
+        };
+
+        let Some(iteration) = iteration.parse().ok().filter(|&i|i != 0) else {
+            return Err(AuthError::InvalidMessage("invalid iteration count"));
+        };
+
+        let cbind_input = [self.gs2_header.as_bytes(), &self.cbind_data].concat();
+        let client_final_without_proof = format!(
+            "c={},r={nonce}",
+            BASE64_STANDARD.encode(cbind_input),
+        );
+
+        let auth_message = format!(
+            "{},{server_first},{client_final_without_proof}",
+            self.client_first_bare,
+        );
+
+        let salted_password = hi(&self.password, &salt, iteration);
+
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key: [u8; 32] = Sha256::digest(client_key).into();
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+
+        let mut client_proof = client_key;
+        for (proof, sign) in client_proof.iter_mut().zip(client_signature) {
+            *proof ^= sign;
+        }
+
+        let server_key = hmac(&salted_password, b"Server Key");
+        let server_signature = hmac(&server_key, auth_message.as_bytes());
+
+        self.state = State::ClientFinal { server_signature };
+
+        Ok(format!(
+            "{client_final_without_proof},p={}",
+            BASE64_STANDARD.encode(client_proof),
+        ))
+    }
+
+    /// Verify `server-final-message`.
+    pub fn verify(&mut self, server_final: &[u8]) -> Result<(), AuthError> {
+        let State::ClientFinal { server_signature } = &self.state else {
+            return Err(AuthError::InvalidMessage("unexpected SASL final"));
+        };
+
+        let server_final = std::str::from_utf8(server_final)
+            .map_err(|_|AuthError::InvalidMessage("non utf8 server-final-message"))?;
+
+        let verifier = match server_final.split(',').next().and_then(|e|e.split_at_checked(2)) {
+            Some(("v=", verifier)) => verifier,
+            Some(("e=", error)) => return Err(AuthError::Server(error.into())),
+            _ => return Err(AuthError::InvalidMessage("invalid server-final-message")),
+        };
+
+        match BASE64_STANDARD.decode(verifier) {
+            Ok(verifier) if verifier == server_signature => {
+                self.state = State::Verified;
+                Ok(())
+            },
+            _ => Err(AuthError::SignatureMismatch),
+        }
+    }
+
+    /// Returns `true` if server signature is verified.
+    pub fn is_verified(&self) -> bool {
+        matches!(self.state, State::Verified)
+    }
+
+    /// Returns `true` if exchange is verified with channel binding.
+    pub fn is_channel_bound(&self) -> bool {
+        self.is_verified() && !self.cbind_data.is_empty()
+    }
+}
+
+/// Returns `true` if given SASL mechanism list contains `mechanism`.
+///
+/// The list is nul separated mechanism names, terminated by an empty name.
+pub fn has_mechanism(list: &[u8], mechanism: &str) -> bool {
+    list.split(|e|*e == b'\0').any(|e|e == mechanism.as_bytes())
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// `Hi()` function, which is essentially PBKDF2 with HMAC-SHA-256.
+fn hi(password: &[u8], salt: &[u8], iteration: u32) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(password).expect("HMAC can take key of any size");
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+
+    let mut u: [u8; 32] = mac.finalize().into_bytes().into();
+    let mut result = u;
+
+    for _ in 1..iteration {
+        u = hmac(password, &u);
+        for (r, u) in result.iter_mut().zip(u) {
+            *r ^= u;
+        }
+    }
+
+    result
+}
+
+/// Normalize password with SASLprep.
+///
+/// As in libpq and postgres, the raw password is used if it contains characters prohibited by
+/// SASLprep, e.g. control characters.
+///
+/// <https://datatracker.ietf.org/doc/html/rfc4013>
+fn saslprep(password: &str) -> String {
+    match stringprep::saslprep(password) {
+        Ok(normalized) => normalized.into_owned(),
+        Err(_) => password.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // <https://datatracker.ietf.org/doc/html/rfc7677#section-3>
+    #[test]
+    fn rfc7677() {
+        let mut scram = Scram::with_nonce("pencil", Binding::Unsupported, "rOprNGfwEbeRWgbNEkqO");
+        assert_eq!(scram.client_first(), "n,,n=,r=rOprNGfwEbeRWgbNEkqO");
+
+        // postgres sends empty username, so the vector is adjusted
+        scram.client_first_bare = "n=user,r=rOprNGfwEbeRWgbNEkqO".into();
+
+        let client_final = scram.client_final(
+            b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+        );
+        assert_eq!(
+            client_final.unwrap(),
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+            p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+        );
+
+        assert!(matches!(
+            scram.verify(b"v=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="),
+            Err(AuthError::SignatureMismatch),
+        ));
+        assert!(!scram.is_verified());
+
+        scram.verify(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=").unwrap();
+        assert!(scram.is_verified());
+    }
+
+    #[test]
+    fn password_saslprep() {
+        for (password, normalized) in [
+            ("pencil", "pencil"),
+            // soft hyphen is mapped to nothing
+            ("I\u{AD}X", "IX"),
+            // non-ASCII space is mapped to space
+            ("a\u{A0}b", "a b"),
+            // NFKC normalization
+            ("\u{2168}", "IX"),
+            ("\u{AA}", "a"),
+            // prohibited character, the raw password is used
+            ("a\u{7}b", "a\u{7}b"),
+        ] {
+            let scram = Scram::with_nonce(password, Binding::Unsupported, "nonce");
+            assert_eq!(scram.password, normalized.as_bytes(), "{password:?}");
+        }
+    }
+}
@@ -1,5 +1,7 @@
 //! Database connection pooling.
-use crate::{Connection, Result, executor::Executor, transport::PgTransport};
+use std::sync::Arc;
+
+use crate::{Config, Connection, Result, connection::CancelToken, executor::Executor, transport::PgTransport};
 
 mod config;
 
@@ -13,6 +15,7 @@ pub use config::PoolConfig;
 #[clippy::has_significant_drop]
 pub struct Pool {
     conn: Option<Connection>,
+    config: Arc<Config>,
     #[cfg(feature = "tokio")]
     handle: worker::WorkerHandle,
     #[cfg(not(feature = "tokio"))]
@@ -31,6 +34,7 @@ impl Clone for Pool {
     fn clone(&self) -> Self {
         Self {
             conn: None,
+            config: self.config.clone(),
             handle: self.handle.clone(),
         }
     }
@@ -60,9 +64,10 @@ impl Pool {
     pub async fn connect_with(config: PoolConfig) -> Result<Self> {
         #[cfg(feature = "tokio")]
         {
+            let conn_config = Arc::new(config.conn.clone());
             let (handle,worker) = worker::WorkerHandle::new(config);
             tokio::spawn(worker);
-            Ok(Self { conn: None, handle })
+            Ok(Self { conn: None, config: conn_config, handle })
         }
 
         #[cfg(not(feature = "tokio"))]
@@ -76,9 +81,10 @@ impl Pool {
     pub fn connect_lazy_with(config: PoolConfig) -> Self {
         #[cfg(feature = "tokio")]
         {
+            let conn_config = Arc::new(config.conn.clone());
             let (handle,worker) = worker::WorkerHandle::new(config);
             tokio::spawn(worker);
-            Self { conn: None, handle }
+            Self { conn: None, config: conn_config, handle }
         }
 
         #[cfg(not(feature = "tokio"))]
@@ -88,6 +94,11 @@ impl Pool {
         }
     }
 
+    /// Returns the connection config.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     fn poll_connection(&mut self, cx: &mut std::task::Context) -> std::task::Poll<Result<Connection>> {
         self.handle.poll_acquire(cx)
     }
@@ -99,7 +110,7 @@ impl Executor for Pool {
     type Future = PoolConnect<'static>;
 
     fn connection(self) -> Self::Future {
-        PoolConnect { pool: Some(PoolCow::Owned(self)) }
+        PoolConnect { pool: Some(PoolCow::Owned(Box::new(self))) }
     }
 }
 
@@ -109,7 +120,7 @@ impl Executor for &Pool {
     type Future = PoolConnect<'static>;
 
     fn connection(self) -> Self::Future {
-        PoolConnect { pool: Some(PoolCow::Owned(self.clone())) }
+        PoolConnect { pool: Some(PoolCow::Owned(Box::new(self.clone()))) }
     }
 }
 
@@ -153,7 +164,7 @@ pub struct PoolConnection<'a> {
 #[derive(Debug)]
 enum PoolCow<'a> {
     Borrow(&'a mut Pool),
-    Owned(Pool),
+    Owned(Box<Pool>),
 }
 
 impl PoolCow<'_> {
@@ -178,6 +189,12 @@ impl PoolConnection<'_> {
         self.pool.as_ref()
     }
 
+    /// Returns a [`CancelToken`] to cancel the query in progress from another task.
+    pub fn cancel_token(&self) -> CancelToken {
+        // `conn` only `None` on drop
+        self.conn.as_ref().unwrap().cancel_token()
+    }
+
     /// Returns the underlying [`Connection`].
     pub fn connection(&mut self) -> &mut Connection {
         // `conn` only `None` on drop
@@ -204,6 +221,31 @@ impl PgTransport for PoolConnection<'_> {
         self.connection().ready_request();
     }
 
+    fn poll_ready(&mut self, cx: &mut std::task::Context) -> std::task::Poll<Result<()>> {
+        self.connection().poll_ready(cx)
+    }
+
+    fn cancel_token(&self) -> Option<CancelToken> {
+        Some(PoolConnection::cancel_token(self))
+    }
+
+    fn query_timeout(&self) -> Option<std::time::Duration> {
+        // `conn` only `None` on drop
+        self.conn.as_ref().unwrap().query_timeout()
+    }
+
+    fn capture_notices(&mut self) {
+        self.connection().capture_notices();
+    }
+
+    fn take_notices(&mut self) -> Vec<crate::postgres::NoticeResponse> {
+        self.connection().take_notices()
+    }
+
+    fn release_notices(&mut self) {
+        self.connection().release_notices();
+    }
+
     fn send<F: crate::postgres::FrontendProtocol>(&mut self, message: F) {
         self.connection().send(message);
     }
@@ -212,11 +254,15 @@ impl PgTransport for PoolConnection<'_> {
         self.connection().send_startup(startup);
     }
 
-    fn get_stmt(&mut self, sql: u64) -> Option<crate::statement::StatementName> {
+    fn get_stmt(&mut self, sql: &str) -> Option<crate::statement::StatementName> {
         self.connection().get_stmt(sql)
     }
 
-    fn add_stmt(&mut self, sql: u64, id: crate::statement::StatementName) {
+    fn next_stmt(&mut self) -> Option<crate::statement::StatementName> {
+        self.connection().next_stmt()
+    }
+
+    fn add_stmt(&mut self, sql: &str, id: crate::statement::StatementName) {
         self.connection().add_stmt(sql, id);
     }
 }
@@ -1,6 +1,9 @@
 use std::time::Duration;
 
-use crate::{Config, Result};
+use crate::{
+    Config, Result,
+    connection::{CredentialProvider, NoticeHandler},
+};
 
 use super::Pool;
 
@@ -15,8 +18,13 @@ pub struct PoolConfig {
 
 impl PoolConfig {
     pub fn from_env() -> PoolConfig {
+        Self::new(Config::from_env())
+    }
+
+    /// Create pool config with given connection config.
+    pub fn new(conn: Config) -> PoolConfig {
         Self {
-            conn: Config::from_env(),
+            conn,
             max_conn: 10,
             retry_delay: Duration::from_secs(5),
             max_retry: 3,
@@ -35,6 +43,34 @@ impl PoolConfig {
         self
     }
 
+    /// Set the [`CredentialProvider`] that is called on each new connection.
+    ///
+    /// Rotated password applies to new connection without rebuilding the [`Pool`].
+    pub fn with_credential_provider(mut self, provider: impl CredentialProvider) -> Self {
+        self.conn = self.conn.with_credential_provider(provider);
+        self
+    }
+
+    /// Set the default time limit of each query, see [`Query::timeout`][1].
+    ///
+    /// [1]: crate::query::Query::timeout
+    pub fn with_query_timeout(mut self, timeout: Duration) -> Self {
+        self.conn = self.conn.with_query_timeout(timeout);
+        self
+    }
+
+    /// Set the prepared statement cache size of each new connection, `0` disables the cache.
+    pub fn with_statement_cache_size(mut self, size: usize) -> Self {
+        self.conn = self.conn.with_statement_cache_size(size);
+        self
+    }
+
+    /// Set the [`NoticeHandler`] of each new connection.
+    pub fn with_notice_handler(mut self, handler: impl NoticeHandler) -> Self {
+        self.conn = self.conn.with_notice_handler(handler);
+        self
+    }
+
     /// Get retry delay.
     pub fn retry_delay(&self) -> Duration {
         self.retry_delay
@@ -53,15 +89,24 @@ impl PoolConfig {
 
 impl PoolConfig {
     pub async fn connect(mut self, url: &str) -> Result<Pool> {
-        let conn = Config::parse(url)?;
-        self.conn = conn;
+        self.parse_url(url)?;
         Pool::connect_with(self).await
     }
 
     pub fn connect_lazy(mut self, url: &str) -> Result<Pool> {
-        let conn = Config::parse(url)?;
-        self.conn = conn;
+        self.parse_url(url)?;
         Ok(Pool::connect_lazy_with(self))
     }
+
+    /// Replace the connection config with `url`, keeping settings that are not part of it.
+    fn parse_url(&mut self, url: &str) -> Result<()> {
+        let mut conn = Config::parse(url)?;
+        conn.credential = self.conn.credential.take();
+        conn.notice_handler = self.conn.notice_handler.take();
+        conn.query_timeout = self.conn.query_timeout;
+        conn.statement_cache_size = self.conn.statement_cache_size;
+        self.conn = conn;
+        Ok(())
+    }
 }
 
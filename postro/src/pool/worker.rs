@@ -20,6 +20,7 @@ use super::PoolConfig;
 use crate::{
     Connection, Result,
     common::{span, verbose},
+    transport::PgTransport,
 };
 
 const HALF_MINUTE: Duration = Duration::from_secs(3);
@@ -81,7 +82,7 @@ impl WorkerHandle {
     }
 
     pub fn release(&self, conn: Connection) {
-        self.send.send(WorkerMessage::Release(conn)).expect("worker task closed");
+        self.send.send(WorkerMessage::Release(Box::new(conn))).expect("worker task closed");
     }
 }
 
@@ -138,10 +139,10 @@ type AcquireRecv = oneshot::Receiver<Result<Connection>>;
 
 enum WorkerMessage {
     Acquire(AcquireSend),
-    Release(Connection),
+    Release(Box<Connection>),
 }
 
-type ConnectFuture = Pin<Box<dyn Future<Output = Result<Connection>> + Send + Sync + 'static>>;
+type ConnectFuture = Pin<Box<dyn Future<Output = Result<Connection>> + Send + 'static>>;
 
 pub struct WorkerFutureV2 {
     config: PoolConfig,
@@ -271,7 +272,7 @@ impl WorkerFutureV2 {
                     span!("release");
                     verbose!("Release");
 
-                    self.healthcheck(conn, cx);
+                    self.healthcheck(*conn, cx);
                 }
             }
         }
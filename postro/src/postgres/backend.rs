@@ -38,6 +38,8 @@ pub enum BackendMessage {
     NoData(NoData),
     /// Identifies the message as a notice.
     NoticeResponse(NoticeResponse),
+    /// Identifies the message as a notification response.
+    NotificationResponse(NotificationResponse),
     /// Identifies the message as a parameter description.
     ParameterDescription(ParameterDescription),
     /// Identifies the message as a run-time parameter status report
@@ -104,6 +106,7 @@ match_backend! {
     NegotiateProtocolVersion,
     NoData,
     NoticeResponse,
+    NotificationResponse,
     ParameterDescription,
     ParameterStatus,
     ParseComplete,
@@ -197,12 +200,14 @@ impl BackendProtocol for Authentication {
 /// Identifies the message as cancellation key data.
 ///
 /// The frontend must save these values if it wishes to be able to issue CancelRequest messages later.
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct BackendKeyData {
     /// The process ID of this backend.
     pub process_id: u32,
     /// The secret key of this backend.
-    pub secret_key: u32,
+    ///
+    /// This is 4 bytes in protocol version 3.0, and variable length up to 256 bytes since 3.2.
+    pub secret_key: Bytes,
 }
 
 msgtype!(BackendKeyData, b'K');
@@ -210,9 +215,13 @@ msgtype!(BackendKeyData, b'K');
 impl BackendProtocol for BackendKeyData {
     fn decode(msgtype: u8, mut body: Bytes) -> Result<Self,ProtocolError> {
         assert_msgtype!(msgtype);
+        // process id followed by 4 to 256 bytes secret key
+        if !(4 + 4..=4 + 256).contains(&body.len()) {
+            return Err(ProtocolError::InvalidSecretKey(body.len().saturating_sub(4)));
+        }
         Ok(Self {
             process_id: body.get_u32(),
-            secret_key: body.get_u32(),
+            secret_key: body,
         })
     }
 }
@@ -275,6 +284,30 @@ impl BackendProtocol for NoticeResponse {
     }
 }
 
+/// Identifies the message as a notification response.
+#[derive(Debug)]
+pub struct NotificationResponse {
+    /// The process ID of the notifying backend process.
+    pub process_id: u32,
+    /// The name of the channel that the notify has been raised on.
+    pub channel: ByteStr,
+    /// The "payload" string passed from the notifying process.
+    pub payload: ByteStr,
+}
+
+msgtype!(NotificationResponse, b'A');
+
+impl BackendProtocol for NotificationResponse {
+    fn decode(msgtype: u8, mut body: Bytes) -> Result<Self,ProtocolError> {
+        assert_msgtype!(msgtype);
+        Ok(Self {
+            process_id: body.get_u32(),
+            channel: body.get_nul_bytestr()?,
+            payload: body.get_nul_bytestr()?,
+        })
+    }
+}
+
 /// Identifies the message as an error.
 pub struct ErrorResponse {
     /// Raw message body.
@@ -412,6 +445,9 @@ impl BackendProtocol for CommandComplete {
 #[derive(Debug)]
 pub struct NegotiateProtocolVersion {
     /// Newest minor protocol version supported by the server for the major protocol version requested by the client.
+    ///
+    /// Note that postgres actually sends the full protocol version number,
+    /// with the major version in the most significant 16 bits.
     pub minor: u32,
     /// Number of protocol options not recognized by the server.
     pub len: u32,
@@ -565,3 +601,42 @@ impl std::fmt::Debug for DataRow {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backend_key_data() {
+        let key = BackendKeyData::decode(b'K', Bytes::from_static(&[0, 0, 0, 42, 1, 2, 3, 4])).unwrap();
+        assert_eq!(key.process_id, 42);
+        assert_eq!(key.secret_key, [1, 2, 3, 4][..]);
+
+        let mut body = vec![0, 0, 0, 42];
+        body.extend([7; 256]);
+        let key = BackendKeyData::decode(b'K', body.clone().into()).unwrap();
+        assert_eq!(key.secret_key.len(), 256);
+
+        body.push(7);
+        for body in [body, vec![0, 0, 0, 42, 1, 2, 3], vec![0, 0]] {
+            let err = BackendKeyData::decode(b'K', body.into()).unwrap_err();
+            assert!(matches!(err, ProtocolError::InvalidSecretKey(_)));
+        }
+    }
+
+    #[test]
+    fn notification_response() {
+        let body = Bytes::from_static(b"\0\0\0\x2ajobs\0{\"id\":1}\0");
+        let notification = NotificationResponse::decode(b'A', body).unwrap();
+        assert_eq!(notification.process_id, 42);
+        assert_eq!(notification.channel, "jobs");
+        assert_eq!(notification.payload, "{\"id\":1}");
+
+        let body = Bytes::from_static(b"\0\0\0\x2ajobs\0\0");
+        let notification = NotificationResponse::decode(b'A', body).unwrap();
+        assert_eq!(notification.channel, "jobs");
+        assert_eq!(notification.payload, "");
+
+        let body = Bytes::from_static(b"\0\0\0\x2a\xffjobs\0\0");
+        assert!(matches!(NotificationResponse::decode(b'A', body), Err(ProtocolError::Utf8Error(_))));
+    }
+}
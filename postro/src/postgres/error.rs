@@ -1,7 +1,7 @@
 //! Protocol error
 use std::{fmt, str::Utf8Error, string::FromUtf8Error};
 
-use super::BackendMessage;
+use super::{BackendMessage, ProtocolVersion};
 
 /// An error when translating buffer from postgres
 pub enum ProtocolError {
@@ -13,6 +13,13 @@ pub enum ProtocolError {
         found: u8,
         phase: Option<&'static str>,
     },
+    /// `BackendKeyData` secret key length is not within 4 to 256 bytes.
+    InvalidSecretKey(usize),
+    /// Server does not support the minimum protocol version requested.
+    UnsupportedVersion {
+        min: ProtocolVersion,
+        found: ProtocolVersion,
+    },
 }
 
 impl BackendMessage {
@@ -25,7 +32,7 @@ impl std::error::Error for ProtocolError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Utf8Error(u) => Some(u),
-            Self::Unexpected { .. } => None,
+            Self::Unexpected { .. } | Self::InvalidSecretKey(_) | Self::UnsupportedVersion { .. } => None,
         }
     }
 }
@@ -51,6 +58,14 @@ impl fmt::Display for ProtocolError {
                 }
                 Ok(())
             },
+            Self::InvalidSecretKey(len) => write!(
+                f,
+                "Invalid `BackendKeyData` secret key length {len}, expected 4 to 256 bytes",
+            ),
+            Self::UnsupportedVersion { min, found } => write!(
+                f,
+                "Server only supports protocol version {found}, but minimum protocol version is {min}",
+            ),
         }
     }
 }
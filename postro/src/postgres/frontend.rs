@@ -63,6 +63,12 @@ pub struct Startup<'a> {
     ///
     /// Value can be true, false, or database, and the default is false.
     pub replication: Option<&'a str>,
+    /// Command-line arguments for the backend.
+    pub options: Option<&'a str>,
+    /// Other run-time parameters to be set at backend start time, e.g. `application_name`.
+    pub params: &'a [(&'a str, &'a str)],
+    /// The requested protocol version.
+    pub version: ProtocolVersion,
 }
 
 impl Startup<'_> {
@@ -77,7 +83,7 @@ impl Startup<'_> {
         // The protocol version number.
         // The most significant 16 bits are the major version number (3 for the protocol described here).
         // The least significant 16 bits are the minor version number (0 for the protocol described here).
-        buf.put_u32(196_608);
+        buf.put_u32(self.version.as_u32());
 
         // The protocol version number is followed by one or more pairs of parameter name and value strings.
 
@@ -98,7 +104,10 @@ impl Startup<'_> {
         //    Spaces within this string are considered to separate arguments,
         //    unless escaped with a backslash (\); write \\ to represent a literal backslash.
 
-        // not supported
+        if let Some(options) = self.options {
+            buf.put_nul_string("options");
+            buf.put_nul_string(options);
+        }
 
 
         // replication: Used to connect in streaming replication mode, where a small set of
@@ -116,6 +125,11 @@ impl Startup<'_> {
         // Such settings will be applied during backend start
         // (after parsing the command-line arguments if any) and will act as session defaults.
 
+        for (name, value) in self.params {
+            buf.put_nul_string(name);
+            buf.put_nul_string(value);
+        }
+
         // A zero byte is required as a terminator after the last name/value pair.
         buf.put_u8(b'\0');
 
@@ -125,6 +139,131 @@ impl Startup<'_> {
     }
 }
 
+/// Postgres protocol version.
+///
+/// Client request the newest minor version it supports in [`Startup`] message,
+/// if server does not support it, server will respond with the newest minor version
+/// it does support via `NegotiateProtocolVersion` message.
+///
+/// <https://www.postgresql.org/docs/current/protocol-overview.html#PROTOCOL-VERSIONS>
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    major: u16,
+    minor: u16,
+}
+
+impl ProtocolVersion {
+    /// Protocol version 3.0, supported by PostgreSQL 7.4 and later.
+    pub const V3_0: ProtocolVersion = ProtocolVersion::new(3, 0);
+
+    /// Protocol version 3.2, supported by PostgreSQL 18 and later.
+    ///
+    /// This version introduce variable length cancel key in `BackendKeyData`.
+    pub const V3_2: ProtocolVersion = ProtocolVersion::new(3, 2);
+
+    /// The newest protocol version supported by `postro`.
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V3_2;
+
+    /// Create new protocol version.
+    pub const fn new(major: u16, minor: u16) -> ProtocolVersion {
+        Self { major, minor }
+    }
+
+    /// Returns the major version number.
+    pub const fn major(&self) -> u16 {
+        self.major
+    }
+
+    /// Returns the minor version number.
+    pub const fn minor(&self) -> u16 {
+        self.minor
+    }
+
+    /// Parse libpq protocol version value, either `3.0`, `3.2` or `latest`.
+    ///
+    /// Returns `None` for invalid or unsupported value. Note that `3.1` is never used
+    /// by postgres, thus it is also rejected.
+    pub fn parse(value: &str) -> Option<ProtocolVersion> {
+        match value {
+            "3.0" => Some(Self::V3_0),
+            "3.2" => Some(Self::V3_2),
+            "latest" => Some(Self::LATEST),
+            _ => None,
+        }
+    }
+
+    /// The protocol version number as sent in startup message.
+    ///
+    /// The most significant 16 bits are the major version number,
+    /// and the least significant 16 bits are the minor version number.
+    pub const fn as_u32(&self) -> u32 {
+        (self.major as u32) << 16 | self.minor as u32
+    }
+}
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        Self::V3_0
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Postgres SSLRequest frontend message
+///
+/// Like [`Startup`], [`SslRequest`] has no initial message-type byte,
+/// thus [`SslRequest`] does not implement [`FrontendProtocol`].
+///
+/// To write ssl request message, use [`SslRequest::write`].
+#[derive(Debug)]
+pub struct SslRequest;
+
+impl SslRequest {
+    pub fn write(self, buf: &mut BytesMut) {
+        // Int32(8) Length of message contents in bytes, including self.
+        buf.put_u32(8);
+        // Int32(80877103)
+        // The SSL request code. The value is chosen to contain 1234 in the most significant 16 bits,
+        // and 5679 in the least significant 16 bits. (To avoid confusion, this code must not be the
+        // same as any protocol version number.)
+        buf.put_u32(80_877_103);
+    }
+}
+
+/// Postgres CancelRequest frontend message
+///
+/// Like [`Startup`], [`CancelRequest`] has no initial message-type byte,
+/// and it is sent over a new connection instead of the one to be cancelled.
+///
+/// To write cancel request message, use [`CancelRequest::write`].
+#[derive(Debug)]
+pub struct CancelRequest<'a> {
+    /// The process ID of the target backend.
+    pub process_id: u32,
+    /// The secret key for the target backend.
+    pub secret_key: &'a [u8],
+}
+
+impl CancelRequest<'_> {
+    pub fn write(self, buf: &mut BytesMut) {
+        // Int32 Length of message contents in bytes, including self.
+        buf.put_u32(12 + self.secret_key.len().to_u32());
+        // Int32(80877102)
+        // The cancel request code. The value is chosen to contain 1234 in the most significant 16 bits,
+        // and 5678 in the least significant 16 bits. (To avoid confusion, this code must not be the
+        // same as any protocol version number.)
+        buf.put_u32(80_877_102);
+        // Int32 The process ID of the target backend.
+        buf.put_u32(self.process_id);
+        // Byten The secret key for the target backend.
+        buf.put_slice(self.secret_key);
+    }
+}
+
 macro_rules! size_of {
     ($s1:tt.$f1:ident as $t1:ty, in ..$s2:tt.$f2:ident) => {
         ($s2.$f2 as u32 * u32::try_from(size_of::<$t1>()).expect("data type size too large for postgres"))
@@ -152,6 +291,49 @@ impl FrontendProtocol for PasswordMessage<'_> {
     }
 }
 
+/// Identifies the message as an initial SASL response.
+#[derive(Debug)]
+pub struct SASLInitialResponse<'a> {
+    /// Name of the SASL authentication mechanism that the client selected.
+    pub mechanism: &'a str,
+    /// SASL mechanism specific "Initial Response".
+    pub data: &'a [u8],
+}
+
+impl FrontendProtocol for SASLInitialResponse<'_> {
+    const MSGTYPE: u8 = b'p';
+
+    fn size_hint(&self) -> u32 {
+        self.mechanism.nul_string_len() + 4 + self.data.len().to_u32()
+    }
+
+    fn encode(self, mut buf: impl BufMut) {
+        buf.put_nul_string(self.mechanism);
+        // Length of SASL mechanism specific "Initial Client Response" that follows,
+        // or -1 if there is no Initial Response.
+        buf.put_i32(self.data.len().to_u32() as i32);
+        buf.put_slice(self.data);
+    }
+}
+
+/// Identifies the message as a SASL response.
+pub struct SASLResponse<'a> {
+    /// SASL mechanism specific message data.
+    pub data: &'a [u8],
+}
+
+impl FrontendProtocol for SASLResponse<'_> {
+    const MSGTYPE: u8 = b'p';
+
+    fn size_hint(&self) -> u32 {
+        self.data.len().to_u32()
+    }
+
+    fn encode(self, mut buf: impl BufMut) {
+        buf.put_slice(self.data);
+    }
+}
+
 /// Identifies the message as a simple query
 #[derive(Debug)]
 pub struct Query<'a> {
@@ -452,3 +634,32 @@ impl fmt::Debug for PasswordMessage<'_> {
     }
 }
 
+impl fmt::Debug for SASLResponse<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SASLResponse")
+            .field("data", &"<REDACTED>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use super::CancelRequest;
+
+    #[test]
+    fn cancel_request() {
+        // protocol 3.0 use 4 bytes key, 3.2 use up to 256 bytes key
+        for key in [&[1, 2, 3, 4][..], &[7; 32]] {
+            let mut buf = BytesMut::new();
+            CancelRequest { process_id: 42, secret_key: key }.write(&mut buf);
+
+            assert_eq!(buf.len(), 12 + key.len());
+            assert_eq!(buf[..4], (12 + key.len() as u32).to_be_bytes());
+            assert_eq!(buf[4..8], 80_877_102u32.to_be_bytes());
+            assert_eq!(buf[8..12], 42u32.to_be_bytes());
+            assert_eq!(&buf[12..], key);
+        }
+    }
+}
@@ -43,7 +43,8 @@ mod error;
 pub use pg_type::{Oid, PgType};
 pub use pg_format::PgFormat;
 
-pub use frontend::FrontendProtocol;
+pub use frontend::{FrontendProtocol, ProtocolVersion};
 pub use backend::{BackendMessage, BackendProtocol, ErrorResponse, NoticeResponse};
 pub use error::ProtocolError;
+pub use notice::MessageFields;
 
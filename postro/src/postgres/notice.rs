@@ -26,6 +26,7 @@ use crate::{
 //
 // previously, this have their own explicit fields, but clippy detect it
 // to big in size, 432 bytes to be exact
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MessageFields {
     /// one of [`Severity`], or a localized translation of one of these, always present
     SeverityLocalized,
@@ -96,13 +97,24 @@ pub enum MessageFields {
 }
 
 impl MessageFields {
+    /// Find the value of `field` in message body.
+    ///
+    /// Returns `None` if the field is not present or is not valid UTF-8.
+    pub fn find(mut body: &[u8], field: MessageFields) -> Option<&str> {
+        while let [key, rest @ ..] = body && *key != b'\0' {
+            let end = rest.iter().position(|e|*e == b'\0')?;
+            if MessageFields::from_byte(*key) == Some(field) {
+                return std::str::from_utf8(&rest[..end]).ok();
+            }
+            body = &rest[end + 1..];
+        }
+        None
+    }
+
     pub fn debug(body: &[u8], f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut map = f.debug_map();
         let mut iter = body.iter().copied().enumerate();
-        loop {
-            let Some((i,key)) = iter.next() else {
-                break;
-            };
+        while let Some((i,key)) = iter.next() {
             let Some(key) = MessageFields::from_byte(key) else {
                 break;
             };
@@ -123,10 +135,7 @@ impl MessageFields {
         let mut hint = None;
 
         let mut iter = body.iter().copied().enumerate();
-        loop {
-            let Some((i,key)) = iter.next() else {
-                break;
-            };
+        while let Some((i,key)) = iter.next() {
             let Some((end,_)) = iter.find(|(_,e)|matches!(e,b'\0')) else {
                 break;
             };
@@ -211,6 +220,50 @@ impl MessageFields {
     }
 }
 
+macro_rules! fields {
+    ($ty:ident) => {
+        impl $ty {
+            /// Returns the value of given field, if present.
+            pub fn field(&self, field: MessageFields) -> Option<&str> {
+                MessageFields::find(&self.body, field)
+            }
+
+            /// Returns the non-localized severity if present, otherwise the localized one.
+            ///
+            /// The severity is one of `ERROR`, `FATAL`, or `PANIC` in an error message,
+            /// or `WARNING`, `NOTICE`, `DEBUG`, `INFO`, or `LOG` in a notice message.
+            pub fn severity(&self) -> &str {
+                self.field(MessageFields::Severity)
+                    .or_else(||self.field(MessageFields::SeverityLocalized))
+                    .unwrap_or_default()
+            }
+
+            /// Returns the SQLSTATE code.
+            pub fn code(&self) -> &str {
+                self.field(MessageFields::Code).unwrap_or_default()
+            }
+
+            /// Returns the primary human-readable message.
+            pub fn message(&self) -> &str {
+                self.field(MessageFields::Message).unwrap_or_default()
+            }
+
+            /// Returns the optional secondary message carrying more detail.
+            pub fn detail(&self) -> Option<&str> {
+                self.field(MessageFields::Detail)
+            }
+
+            /// Returns the optional suggestion what to do about the problem.
+            pub fn hint(&self) -> Option<&str> {
+                self.field(MessageFields::Hint)
+            }
+        }
+    };
+}
+
+fields!(ErrorResponse);
+fields!(NoticeResponse);
+
 impl std::error::Error for ErrorResponse { }
 
 impl std::fmt::Debug for ErrorResponse {
@@ -241,3 +294,22 @@ impl std::fmt::Display for NoticeResponse {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::MessageFields;
+    use crate::postgres::NoticeResponse;
+
+    #[test]
+    fn notice_fields() {
+        let notice = NoticeResponse::new(
+            b"SNOTICE\0VNOTICE\0C00000\0Mmigrating users\0Hrun later\0\0"[..].into(),
+        );
+        assert_eq!(notice.severity(), "NOTICE");
+        assert_eq!(notice.code(), "00000");
+        assert_eq!(notice.message(), "migrating users");
+        assert_eq!(notice.detail(), None);
+        assert_eq!(notice.hint(), Some("run later"));
+        assert_eq!(notice.field(MessageFields::Routine), None);
+    }
+}
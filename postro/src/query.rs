@@ -1,5 +1,5 @@
 //! Query API types.
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::Duration};
 
 use crate::{
     Decode, FromRow, Result, Row,
@@ -14,19 +14,19 @@ use crate::{
 /// Entrypoint of the query API.
 #[inline]
 pub fn query<'val, SQL, Exe>(sql: SQL, exe: Exe) -> Query<'val, SQL, Exe, StreamRow<Row>> {
-    Query { sql, exe, params: Vec::new(), _p: PhantomData }
+    Query { sql, exe, params: Vec::new(), timeout: None, _p: PhantomData }
 }
 
 /// Entrypoint of the query API.
 #[inline]
 pub fn query_as<'val, SQL, Exe, R>(sql: SQL, exe: Exe) -> Query<'val, SQL, Exe, StreamRow<R>> {
-    Query { sql, exe, params: Vec::new(), _p: PhantomData }
+    Query { sql, exe, params: Vec::new(), timeout: None, _p: PhantomData }
 }
 
 /// Entrypoint of the query API.
 #[inline]
 pub fn query_scalar<'val, SQL, Exe, D>(sql: SQL, exe: Exe) -> Query<'val, SQL, Exe, StreamScalar<D>> {
-    Query { sql, exe, params: Vec::new(), _p: PhantomData }
+    Query { sql, exe, params: Vec::new(), timeout: None, _p: PhantomData }
 }
 
 /// The query API.
@@ -36,6 +36,7 @@ pub struct Query<'val, SQL, Exe, M> {
     sql: SQL,
     exe: Exe,
     params: Vec<Encoded<'val>>,
+    timeout: Option<Duration>,
     _p: PhantomData<M>,
 }
 
@@ -46,6 +47,26 @@ impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
         self.params.push(value.encode());
         self
     }
+
+    /// Set the time limit of the query, overriding the connection default.
+    ///
+    /// If the time limit elapsed while waiting for the server, the query is cancelled using the
+    /// [`CancelToken`][1], and the connection is drained until the server is ready for the next
+    /// query, then [`ErrorKind::Timeout`][2] is returned.
+    ///
+    /// The time limit starts after the connection is acquired.
+    ///
+    /// # Panics
+    ///
+    /// Polling the query panics if `tokio` feature is not enabled.
+    ///
+    /// [1]: crate::connection::CancelToken
+    /// [2]: crate::ErrorKind::Timeout
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 }
 
 impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
@@ -62,7 +83,7 @@ impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
         Exe: Executor,
         M: StreamMap,
     {
-        FetchStream::new(self.sql, self.exe.connection(), self.params, 0)
+        FetchStream::new(self.sql, self.exe.connection(), self.params, 0, self.timeout)
     }
 
     /// Fetch all rows into [`Vec`].
@@ -78,6 +99,7 @@ impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
             self.params,
             CollectAll(Vec::new()),
             0,
+            self.timeout,
         )
     }
 
@@ -94,6 +116,7 @@ impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
             self.params,
             CollectOne(None),
             1,
+            self.timeout,
         )
     }
 
@@ -110,6 +133,7 @@ impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
             self.params,
             CollectOpt(None),
             1,
+            self.timeout,
         )
     }
 
@@ -119,7 +143,7 @@ impl<'val, SQL, Exe, M> Query<'val, SQL, Exe, M> {
     where
         Exe: Executor,
     {
-        Fetch::new(self.sql, self.exe.connection(), self.params, CollectCmd, 0)
+        Fetch::new(self.sql, self.exe.connection(), self.params, CollectCmd, 0, self.timeout)
     }
 }
 
@@ -1,38 +1,40 @@
-use std::sync::atomic::Ordering;
-
-type AtomicId = std::sync::atomic::AtomicU16;
+/// Length of `q` prefix and the maximum digits of `u64`.
+const ID_LEN: usize = 1 + 20;
 
 #[derive(Clone, PartialEq, Eq)]
-pub struct Id([u8; 6]);
+pub struct Id {
+    buf: [u8; ID_LEN],
+    len: u8,
+}
 
 impl Id {
     pub(crate) fn unnamed() -> Self {
-        Self([b'?'; 6])
+        Self { buf: [0; ID_LEN], len: 0 }
     }
 
-    pub(crate) fn next(atomic: &AtomicId) -> Self {
-        let id = atomic.fetch_add(1, Ordering::SeqCst);
-        let mut buf = [b'q', b'0',b'0',b'0',b'0',b'0'];
-        let len = buf.len();
+    /// Increment `counter` and returns the new id.
+    ///
+    /// The `u64` counter never wraps in practice, so the id is unique to the counter owner.
+    pub(crate) fn next(counter: &mut u64) -> Self {
+        *counter += 1;
 
         let mut b = itoa::Buffer::new();
-        let id = b.format(id);
-        let i = id.as_bytes();
-        buf[len - i.len()..].copy_from_slice(i);
+        let id = b.format(*counter).as_bytes();
+
+        let mut buf = [0; ID_LEN];
+        buf[0] = b'q';
+        buf[1..1 + id.len()].copy_from_slice(id);
 
-        Self(buf)
+        Self { buf, len: (1 + id.len()) as u8 }
     }
 
     pub fn as_str(&self) -> &str {
-        if self.is_unnamed() {
-            return "";
-        }
         // SAFETY: string only construction and is immutable
-        unsafe { std::str::from_utf8_unchecked(&self.0[..]) }
+        unsafe { std::str::from_utf8_unchecked(&self.buf[..self.len as usize]) }
     }
 
     pub fn is_unnamed(&self) -> bool {
-        self.0[0] == b'?'
+        self.len == 0
     }
 }
 
@@ -65,9 +67,8 @@ macro_rules! delegate {
             }
 
             #[allow(unused, reason = "Portal `next` used later")]
-            pub(crate) fn next() -> Self {
-                static ID: AtomicId = AtomicId::new(0);
-                Self(Id::next(&ID))
+            pub(crate) fn next(counter: &mut u64) -> Self {
+                Self(Id::next(counter))
             }
         }
 
@@ -103,3 +104,23 @@ pub trait Table {
     const INSERT: &str;
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn id() {
+        let mut counter = 0;
+        assert_eq!(Id::next(&mut counter).as_str(), "q1");
+        assert_eq!(Id::next(&mut counter).as_str(), "q2");
+
+        let mut counter = u64::MAX - 1;
+        let id = Id::next(&mut counter);
+        assert_eq!(id.as_str(), "q18446744073709551615");
+        assert_eq!(id.as_str().len(), ID_LEN);
+        assert!(!id.is_unnamed());
+
+        assert_eq!(Id::unnamed().as_str(), "");
+        assert!(Id::unnamed().is_unnamed());
+    }
+}
@@ -1,10 +1,11 @@
 //! The [`Transaction`] type.
-use std::io;
+use std::{io, time::Duration};
 
 use crate::{
     Result,
+    connection::CancelToken,
     postgres::{
-        BackendProtocol, backend,
+        BackendProtocol, NoticeResponse, backend,
         frontend::{self, FrontendProtocol},
     },
     statement::StatementName,
@@ -25,7 +26,7 @@ use crate::{
 /// # async fn test(mut conn: postro::Connection) -> postro::Result<()> {
 /// let mut tx = postro::begin(&mut conn).await?;
 ///
-/// postro::execute("insert into post(name) values('foo')", &mut tx)
+/// postro::query("insert into post(name) values('foo')", &mut tx)
 ///     .execute()
 ///     .await?;
 ///
@@ -86,6 +87,30 @@ where
         IO::ready_request(&mut self.io)
     }
 
+    fn poll_ready(&mut self, cx: &mut std::task::Context) -> std::task::Poll<Result<()>> {
+        IO::poll_ready(&mut self.io, cx)
+    }
+
+    fn cancel_token(&self) -> Option<CancelToken> {
+        IO::cancel_token(&self.io)
+    }
+
+    fn query_timeout(&self) -> Option<Duration> {
+        IO::query_timeout(&self.io)
+    }
+
+    fn capture_notices(&mut self) {
+        IO::capture_notices(&mut self.io)
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        IO::take_notices(&mut self.io)
+    }
+
+    fn release_notices(&mut self) {
+        IO::release_notices(&mut self.io)
+    }
+
     fn send<F: FrontendProtocol>(&mut self, message: F) {
         IO::send(&mut self.io, message)
     }
@@ -94,11 +119,15 @@ where
         IO::send_startup(&mut self.io, startup)
     }
 
-    fn get_stmt(&mut self, sql: u64) -> Option<StatementName> {
+    fn get_stmt(&mut self, sql: &str) -> Option<StatementName> {
         IO::get_stmt(&mut self.io, sql)
     }
 
-    fn add_stmt(&mut self, sql: u64, id: StatementName) {
+    fn next_stmt(&mut self) -> Option<StatementName> {
+        IO::next_stmt(&mut self.io)
+    }
+
+    fn add_stmt(&mut self, sql: &str, id: StatementName) {
         IO::add_stmt(&mut self.io, sql, id)
     }
 }
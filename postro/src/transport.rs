@@ -2,11 +2,13 @@
 use std::{
     io,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use crate::{
     Result,
-    postgres::{BackendProtocol, FrontendProtocol, frontend},
+    connection::CancelToken,
+    postgres::{BackendProtocol, FrontendProtocol, NoticeResponse, frontend},
     statement::StatementName,
 };
 
@@ -29,6 +31,54 @@ pub trait PgTransport: Unpin {
     /// Request implementor to ignore all backend messages until `ReadyForQuery` is received.
     fn ready_request(&mut self);
 
+    /// Poll to ignore backend messages requested by [`ready_request`][1].
+    ///
+    /// Calling `poll_ready` will also try to [`poll_flush`][2] if there is buffered message.
+    ///
+    /// The default implementation does nothing, requested messages are ignored by the
+    /// subsequent [`poll_recv`][3].
+    ///
+    /// [1]: PgTransport::ready_request
+    /// [2]: PgTransport::poll_flush
+    /// [3]: PgTransport::poll_recv
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        let _ = cx;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Returns a [`CancelToken`] to cancel the query in progress, if supported.
+    ///
+    /// The default implementation returns `None`.
+    fn cancel_token(&self) -> Option<CancelToken> {
+        None
+    }
+
+    /// Returns the default time limit of a query.
+    ///
+    /// The default implementation returns `None`.
+    fn query_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Start collecting `NoticeResponse` instead of passing it to the notice handler.
+    ///
+    /// The default implementation does nothing.
+    fn capture_notices(&mut self) { }
+
+    /// Stop collecting `NoticeResponse` and returns the collected notices.
+    ///
+    /// The default implementation returns empty notices.
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        vec![]
+    }
+
+    /// Stop collecting `NoticeResponse` and pass the collected notices to the notice handler.
+    ///
+    /// The default implementation discards the collected notices.
+    fn release_notices(&mut self) {
+        self.take_notices();
+    }
+
     /// Send message to the backend.
     ///
     /// Note that this send is buffered, caller must also call
@@ -48,11 +98,27 @@ pub trait PgTransport: Unpin {
     /// [1]: frontend::Startup
     fn send_startup(&mut self, startup: frontend::Startup);
 
-    /// Check for already prepared statement.
-    fn get_stmt(&mut self, sql: u64) -> Option<StatementName>;
+    /// Check for already prepared statement of exactly the same `sql`.
+    ///
+    /// The default implementation returns `None`.
+    fn get_stmt(&mut self, sql: &str) -> Option<StatementName> {
+        let _ = sql;
+        None
+    }
+
+    /// Allocate a new prepared statement name, returns `None` if statement cache is disabled.
+    ///
+    /// The default implementation returns `None`, all queries use the unnamed statement.
+    fn next_stmt(&mut self) -> Option<StatementName> {
+        None
+    }
 
     /// Add new prepared statement.
-    fn add_stmt(&mut self, sql: u64, id: StatementName);
+    ///
+    /// The default implementation does nothing.
+    fn add_stmt(&mut self, sql: &str, id: StatementName) {
+        let _ = (sql, id);
+    }
 }
 
 impl<P> PgTransport for &mut P where P: PgTransport {
@@ -68,6 +134,30 @@ impl<P> PgTransport for &mut P where P: PgTransport {
         P::ready_request(self);
     }
 
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        P::poll_ready(self, cx)
+    }
+
+    fn cancel_token(&self) -> Option<CancelToken> {
+        P::cancel_token(self)
+    }
+
+    fn query_timeout(&self) -> Option<Duration> {
+        P::query_timeout(self)
+    }
+
+    fn capture_notices(&mut self) {
+        P::capture_notices(self);
+    }
+
+    fn take_notices(&mut self) -> Vec<NoticeResponse> {
+        P::take_notices(self)
+    }
+
+    fn release_notices(&mut self) {
+        P::release_notices(self);
+    }
+
     fn send<F: FrontendProtocol>(&mut self, message: F) {
         P::send(self, message);
     }
@@ -76,11 +166,15 @@ impl<P> PgTransport for &mut P where P: PgTransport {
         P::send_startup(self, startup);
     }
 
-    fn get_stmt(&mut self, sql: u64) -> Option<StatementName> {
+    fn get_stmt(&mut self, sql: &str) -> Option<StatementName> {
         P::get_stmt(self, sql)
     }
 
-    fn add_stmt(&mut self, sql: u64, id: StatementName) {
+    fn next_stmt(&mut self) -> Option<StatementName> {
+        P::next_stmt(self)
+    }
+
+    fn add_stmt(&mut self, sql: &str, id: StatementName) {
         P::add_stmt(self, sql, id);
     }
 }
@@ -100,3 +194,144 @@ pub trait PgTransportExt: PgTransport {
 
 impl<T> PgTransportExt for T where T: PgTransport { }
 
+#[cfg(test)]
+pub(crate) mod test {
+    use bytes::{Buf, Bytes, BytesMut};
+    use std::{collections::VecDeque, pin::pin};
+
+    use super::*;
+    use crate::postgres::backend;
+
+    /// A [`PgTransport`] which replay scripted backend messages and record sent frontend messages.
+    pub struct ScriptedTransport {
+        /// `None` is a silent backend, which is skipped only while ignoring messages
+        recv: VecDeque<Option<(u8, Bytes)>>,
+        write_buf: BytesMut,
+        startup: bool,
+        ready: usize,
+    }
+
+    impl ScriptedTransport {
+        pub fn new() -> Self {
+            Self { recv: VecDeque::new(), write_buf: BytesMut::new(), startup: false, ready: 0 }
+        }
+
+        /// Queue a backend message.
+        pub fn push(&mut self, msgtype: u8, body: impl Into<Bytes>) {
+            self.recv.push_back(Some((msgtype, body.into())));
+        }
+
+        /// Queue a silent backend, `poll_recv` returns `Pending` until `ReadyForQuery` is requested.
+        #[cfg(feature = "tokio")]
+        pub fn push_pending(&mut self) {
+            self.recv.push_back(None);
+        }
+
+        /// Queue a `RowDescription` of single `text` column.
+        pub fn push_text_column(&mut self) {
+            self.push(b'T', text_column());
+        }
+
+        /// Queue a `DataRow` of single column.
+        pub fn push_text_row(&mut self, value: &str) {
+            self.push(b'D', text_row(value));
+        }
+
+        /// Queue complete responses of unnamed statement query returning single text row.
+        pub fn push_query(&mut self, value: &str) {
+            self.push(b'1', &b""[..]);
+            self.push(b'2', &b""[..]);
+            self.push_text_column();
+            self.push_text_row(value);
+            self.push(b'C', &b"SELECT 1\0"[..]);
+            self.push(b'Z', &b"I"[..]);
+        }
+
+        /// Returns all sent frontend messages, excluding startup message.
+        pub fn sent(&self) -> Vec<(u8, Bytes)> {
+            let mut buf = Bytes::copy_from_slice(&self.write_buf);
+            let mut messages = vec![];
+            if self.startup {
+                let len = buf.get_u32() as usize;
+                buf.advance(len - 4);
+            }
+            while buf.has_remaining() {
+                let msgtype = buf.get_u8();
+                let len = buf.get_u32() as usize;
+                messages.push((msgtype, buf.split_to(len - 4)));
+            }
+            messages
+        }
+
+        fn pop(&mut self) -> Option<(u8, Bytes)> {
+            self.recv.pop_front().expect("backend script exhausted")
+        }
+    }
+
+    impl PgTransport for ScriptedTransport {
+        fn poll_flush(&mut self, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_recv<B: BackendProtocol>(&mut self, cx: &mut Context) -> Poll<Result<B>> {
+            std::task::ready!(self.poll_ready(cx)?);
+            if let Some(None) = self.recv.front() {
+                return Poll::Pending;
+            }
+            let (msgtype, body) = self.pop().unwrap();
+            Poll::Ready(Ok(B::decode(msgtype, body)?))
+        }
+
+        fn ready_request(&mut self) {
+            self.ready += 1;
+        }
+
+        fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<()>> {
+            while self.ready != 0 {
+                if let Some((backend::ReadyForQuery::MSGTYPE, _)) = self.pop() {
+                    self.ready -= 1;
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn send<F: FrontendProtocol>(&mut self, message: F) {
+            frontend::write(message, &mut self.write_buf);
+        }
+
+        fn send_startup(&mut self, startup: frontend::Startup) {
+            self.startup = true;
+            startup.write(&mut self.write_buf);
+        }
+    }
+
+    /// Returns `RowDescription` body of single `text` column.
+    pub fn text_column() -> Vec<u8> {
+        let mut body = vec![0, 1];
+        body.extend_from_slice(b"value\0");
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&0u16.to_be_bytes());
+        body.extend_from_slice(&25u32.to_be_bytes());
+        body.extend_from_slice(&(-1i16).to_be_bytes());
+        body.extend_from_slice(&(-1i32).to_be_bytes());
+        body.extend_from_slice(&1u16.to_be_bytes());
+        body
+    }
+
+    /// Returns `DataRow` body of single column.
+    pub fn text_row(value: &str) -> Vec<u8> {
+        let mut body = vec![0, 1];
+        body.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        body.extend_from_slice(value.as_bytes());
+        body
+    }
+
+    /// Poll future to completion, the future must never returns `Pending`.
+    pub fn block_on<F: Future>(f: F) -> F::Output {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        match pin!(f).poll(&mut cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("scripted future is pending"),
+        }
+    }
+}